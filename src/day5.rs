@@ -1,5 +1,19 @@
+use itertools::Itertools;
+use lazy_static::lazy_static;
 use regex::Regex;
-use std::fs;
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    time::Instant,
+};
+
+lazy_static! {
+    static ref INSTRUCTION_REGEX: Regex = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
+}
+
+/// Yards `plan` visits before giving up, the search being exhaustive.
+const PLAN_MAX_YARDS: usize = 1_000_000;
 
 pub fn part1() {
     let (crates, instructions) = split_input(&input());
@@ -35,6 +49,165 @@ pub fn part2() {
     println!("day5, part2: {:?}", answer);
 }
 
+/// Times the batch crane on `crates` crates spread over 9 stacks and `moves`
+/// random moves, with `Vec` stacks and with the `TreapYard`.
+pub fn bench(crates: usize, moves: usize) {
+    let yard: Vec<Vec<char>> = (0..9)
+        .map(|stack| {
+            (0..crates / 9)
+                .map(|height| (b'A' + ((stack + height) % 26) as u8) as char)
+                .collect()
+        })
+        .collect();
+
+    let mut heights: Vec<usize> = yard.iter().map(Vec::len).collect();
+    let mut seed: u64 = 1996;
+    let mut random = |bound: usize| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % bound
+    };
+    let instructions: Vec<_> = (0..moves)
+        .map(|_| {
+            let loaded: Vec<usize> = (0..9).filter(|&stack| heights[stack] > 0).collect();
+            let from = loaded[random(loaded.len())];
+            let to = (from + 1 + random(8)) % 9;
+            let mov = 1 + random(heights[from]);
+            heights[from] -= mov;
+            heights[to] += mov;
+            (mov, from + 1, to + 1)
+        })
+        .collect();
+
+    let start = Instant::now();
+    let vec_tops: String = instructions
+        .iter()
+        .copied()
+        .fold(yard.clone(), apply_insane_instruction)
+        .into_iter()
+        .filter_map(|column| column.last().copied())
+        .collect();
+    let vec_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let mut treap = TreapYard::from(yard);
+    instructions
+        .iter()
+        .for_each(|&instruction| treap.apply_insane_instruction(instruction));
+    let treap_tops = treap.tops();
+    let treap_elapsed = start.elapsed();
+
+    assert_eq!(vec_tops, treap_tops);
+    println!(
+        "day5, bench: {} crates, {} moves, Vec {:?}, TreapYard {:?}",
+        crates, moves, vec_elapsed, treap_elapsed
+    );
+}
+
+pub fn part1_streaming() {
+    let answer = run_streaming(BufReader::new(File::open("day5").unwrap()), false);
+    println!("day5, part1: {:?}", answer);
+}
+
+pub fn part2_streaming() {
+    let answer = run_streaming(BufReader::new(File::open("day5").unwrap()), true);
+    println!("day5, part2: {:?}", answer);
+}
+
+/// Recovers the starting yard from the final yard drawn in `final_yard`, the
+/// instructions being the puzzle ones.
+pub fn reverse(insane: bool, final_yard: &str) {
+    let final_crates = parse_yard(&fs::read_to_string(final_yard).unwrap());
+    let (_, instructions) = split_input(&input());
+    let instructions: Vec<_> = instructions.split('\n').map(parse_instruction).collect();
+
+    let answer = if insane {
+        reverse_solve(final_crates, &instructions, unapply_insane_instruction)
+    } else {
+        reverse_solve(final_crates, &instructions, unapply_instruction)
+    };
+
+    match answer {
+        Some(crates) => print!("{}", format_yard(&crates)),
+        None => println!("day5, reverse: final yard is unreachable"),
+    }
+}
+
+pub fn optimize() {
+    let (crates, instructions) = split_input(&input());
+    let crates = parse_crates(&crates);
+    let instructions: Vec<_> = instructions.split('\n').map(parse_instruction).collect();
+
+    let optimized = optimize_program(&crates, &instructions, merge_instruction);
+    println!(
+        "day5, optimize part1: {} -> {} moves, equivalent: {:?}",
+        instructions.len(),
+        optimized.len(),
+        equivalent_programs(&crates, &instructions, &optimized, apply_instruction)
+    );
+
+    let optimized = optimize_program(&crates, &instructions, merge_insane_instruction);
+    println!(
+        "day5, optimize part2: {} -> {} moves, equivalent: {:?}",
+        instructions.len(),
+        optimized.len(),
+        equivalent_programs(&crates, &instructions, &optimized, apply_insane_instruction)
+    );
+}
+
+pub fn provenance(insane: bool, stack: Option<usize>) {
+    let (crates, instructions) = split_input(&input());
+    let crates = track_crates(&parse_crates(&crates));
+    let instructions: Vec<_> = instructions.split('\n').map(parse_instruction).collect();
+
+    let crates = if insane {
+        simulate_tracked(crates, &instructions, apply_insane_instruction)
+    } else {
+        simulate_tracked(crates, &instructions, apply_instruction)
+    };
+
+    crates
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| stack.is_none_or(|stack| stack == index + 1))
+        .for_each(|(index, column)| {
+            column.iter().enumerate().for_each(|(height, tracked)| {
+                println!(
+                    "day5, provenance: {} at {}/{} from {}/{}, moves {:?}",
+                    tracked.label,
+                    index + 1,
+                    height + 1,
+                    tracked.stack,
+                    tracked.height,
+                    tracked.moves
+                );
+            })
+        });
+}
+
+pub fn plan(insane: bool, start: &str, target: &str) {
+    let start = parse_yard(&fs::read_to_string(start).unwrap());
+    let target = parse_yard(&fs::read_to_string(target).unwrap());
+
+    let program = if insane {
+        plan_instructions(start, &target, apply_insane_instruction, PLAN_MAX_YARDS)
+    } else {
+        plan_instructions(start, &target, apply_instruction, PLAN_MAX_YARDS)
+    };
+
+    match program {
+        Ok(program) => program
+            .into_iter()
+            .for_each(|instruction| println!("{}", format_instruction(instruction))),
+        Err(PlanError::Unreachable) => println!("day5, plan: target yard is unreachable"),
+        Err(PlanError::TooManyYards(yards)) => println!(
+            "day5, plan: gave up after {} yards, exact search only works for small yards",
+            yards
+        ),
+    }
+}
+
 fn split_input<'a>(input: &str) -> (String, String) {
     let regex = Regex::new(r"([\S\s]+)\n 1 .+\n([\S\s]+)").unwrap();
     let groups = regex.captures(input).unwrap();
@@ -58,6 +231,49 @@ fn parse_crates(crates: &str) -> Vec<Vec<char>> {
     })
 }
 
+/// A yard drawing on its own, crate rows followed by the stack numbers line.
+/// Rows may have their trailing spaces trimmed.
+fn parse_yard(yard: &str) -> Vec<Vec<char>> {
+    let rows: Vec<&str> = yard
+        .split('\n')
+        .take_while(|line| !line.starts_with(" 1"))
+        .collect();
+    let stacks = yard
+        .split('\n')
+        .nth(rows.len())
+        .map_or(0, |numbers| numbers.split_whitespace().count());
+    let width = (stacks * 4).saturating_sub(1);
+
+    let crates = rows
+        .into_iter()
+        .map(|row| format!("{:<width$}", row, width = width))
+        .join("\n");
+
+    parse_crates(&crates)
+}
+
+/// Draws the yard the way `parse_yard` reads it.
+fn format_yard(crates: &[Vec<char>]) -> String {
+    let height = crates.iter().map(Vec::len).max().unwrap_or(0);
+
+    let rows = (0..height).rev().map(|level| {
+        crates
+            .iter()
+            .map(|column| match column.get(level) {
+                Some(label) => format!("[{}]", label),
+                None => "   ".to_string(),
+            })
+            .join(" ")
+    });
+    let numbers = (1..=crates.len())
+        .map(|stack| format!(" {} ", stack))
+        .join(" ");
+
+    rows.chain(std::iter::once(numbers))
+        .map(|row| format!("{}\n", row))
+        .collect()
+}
+
 fn parse_crates_row(crates_row: &str) -> Vec<Vec<char>> {
     let crates_row = format!("{} ", crates_row);
     crates_row
@@ -68,8 +284,7 @@ fn parse_crates_row(crates_row: &str) -> Vec<Vec<char>> {
 }
 
 fn parse_instruction(instruction: &str) -> (usize, usize, usize) {
-    let regex = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-    let groups = regex.captures(instruction).unwrap();
+    let groups = INSTRUCTION_REGEX.captures(instruction).unwrap();
 
     (
         groups[1].parse().unwrap(),
@@ -78,10 +293,14 @@ fn parse_instruction(instruction: &str) -> (usize, usize, usize) {
     )
 }
 
-fn apply_instruction(
-    mut chars: Vec<Vec<char>>,
+fn format_instruction((mov, from, to): (usize, usize, usize)) -> String {
+    format!("move {} from {} to {}", mov, from, to)
+}
+
+fn apply_instruction<T>(
+    mut chars: Vec<Vec<T>>,
     (mov, from, to): (usize, usize, usize),
-) -> Vec<Vec<char>> {
+) -> Vec<Vec<T>> {
     let from = from - 1;
     let to = to - 1;
 
@@ -95,10 +314,10 @@ fn apply_instruction(
     chars
 }
 
-fn apply_insane_instruction(
-    mut chars: Vec<Vec<char>>,
+fn apply_insane_instruction<T>(
+    mut chars: Vec<Vec<T>>,
     (mov, from, to): (usize, usize, usize),
-) -> Vec<Vec<char>> {
+) -> Vec<Vec<T>> {
     let from = from - 1;
     let to = to - 1;
 
@@ -109,10 +328,496 @@ fn apply_insane_instruction(
     chars
 }
 
+fn unapply_instruction(
+    mut chars: Vec<Vec<char>>,
+    (mov, from, to): (usize, usize, usize),
+) -> Option<Vec<Vec<char>>> {
+    let from = from - 1;
+    let to = to - 1;
+
+    for _ in 0..mov {
+        let c = chars[to].pop()?;
+        chars[from].push(c);
+    }
+
+    Some(chars)
+}
+
+fn unapply_insane_instruction(
+    mut chars: Vec<Vec<char>>,
+    (mov, from, to): (usize, usize, usize),
+) -> Option<Vec<Vec<char>>> {
+    let from = from - 1;
+    let to = to - 1;
+
+    let moving_index = chars[to].len().checked_sub(mov)?;
+    let moving_crates: Vec<_> = chars[to].drain(moving_index..).collect();
+    chars[from].extend(moving_crates);
+
+    Some(chars)
+}
+
+/// Runs the instructions backwards from the final yard, `None` when the yard
+/// could not have been produced by them (a stack runs out of crates).
+fn reverse_solve<F>(
+    final_crates: Vec<Vec<char>>,
+    instructions: &[(usize, usize, usize)],
+    unapply: F,
+) -> Option<Vec<Vec<char>>>
+where
+    F: FnMut(Vec<Vec<char>>, (usize, usize, usize)) -> Option<Vec<Vec<char>>>,
+{
+    instructions
+        .iter()
+        .rev()
+        .copied()
+        .try_fold(final_crates, unapply)
+}
+
+/// Two consecutive moves of the one-by-one crane between the same two stacks
+/// only amount to their net transfer, and single crates can skip a stack.
+/// Only valid when both moves find all their crates.
+fn merge_instruction(
+    (mov_a, from_a, to_a): (usize, usize, usize),
+    (mov_b, from_b, to_b): (usize, usize, usize),
+) -> Option<(usize, usize, usize)> {
+    if from_a == from_b && to_a == to_b {
+        Some((mov_a + mov_b, from_a, to_a))
+    } else if from_a == to_b && to_a == from_b {
+        if mov_a >= mov_b {
+            Some((mov_a - mov_b, from_a, to_a))
+        } else {
+            Some((mov_b - mov_a, from_b, to_b))
+        }
+    } else if mov_a == 1 && mov_b == 1 && to_a == from_b {
+        Some((1, from_a, to_b))
+    } else {
+        None
+    }
+}
+
+/// The batch crane keeps the moved block in order, so moving the same block
+/// twice in a row is a single move (or none when it comes back). Only valid
+/// when both moves find all their crates.
+fn merge_insane_instruction(
+    (mov_a, from_a, to_a): (usize, usize, usize),
+    (mov_b, from_b, to_b): (usize, usize, usize),
+) -> Option<(usize, usize, usize)> {
+    if mov_a == mov_b && to_a == from_b {
+        Some((mov_a, from_a, to_b))
+    } else {
+        None
+    }
+}
+
+fn is_noop_instruction((mov, from, to): (usize, usize, usize)) -> bool {
+    mov == 0 || from == to
+}
+
+/// Merges consecutive instructions starting from `crates`. Stack heights are
+/// followed along the way: a move short of crates is kept as is, since the
+/// merge rules assume every crate asked for is moved. Merging two full moves
+/// gives a full move, so the heights of the original program still hold.
+fn optimize_program<F>(
+    crates: &[Vec<char>],
+    instructions: &[(usize, usize, usize)],
+    merge: F,
+) -> Vec<(usize, usize, usize)>
+where
+    F: Fn((usize, usize, usize), (usize, usize, usize)) -> Option<(usize, usize, usize)>,
+{
+    let mut heights: Vec<usize> = crates.iter().map(Vec::len).collect();
+
+    instructions
+        .iter()
+        .copied()
+        .fold(Vec::new(), |mut program, mut instruction| {
+            let (mov, from, to) = instruction;
+            let full = heights[from - 1] >= mov;
+            let moved = mov.min(heights[from - 1]);
+            heights[from - 1] -= moved;
+            heights[to - 1] += moved;
+
+            while full && !is_noop_instruction(instruction) {
+                match program.last() {
+                    Some(&(last, true)) => match merge(last, instruction) {
+                        Some(merged) => {
+                            program.pop();
+                            instruction = merged;
+                        }
+                        None => break,
+                    },
+                    _ => break,
+                }
+            }
+
+            if !is_noop_instruction(instruction) {
+                program.push((instruction, full));
+            }
+            program
+        })
+        .into_iter()
+        .map(|(instruction, _)| instruction)
+        .collect()
+}
+
+fn equivalent_programs<F>(
+    crates: &[Vec<char>],
+    program_a: &[(usize, usize, usize)],
+    program_b: &[(usize, usize, usize)],
+    apply: F,
+) -> bool
+where
+    F: Fn(Vec<Vec<char>>, (usize, usize, usize)) -> Vec<Vec<char>>,
+{
+    let crates_a = program_a.iter().copied().fold(crates.to_vec(), &apply);
+    let crates_b = program_b.iter().copied().fold(crates.to_vec(), &apply);
+
+    crates_a == crates_b
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PlanError {
+    Unreachable,
+    TooManyYards(usize),
+}
+
+/// Breadth first search over the yards reachable with the given crane, so the
+/// first program reaching the target is a shortest one. The number of yards
+/// grows exponentially with the crates, the search stops after `max_yards`.
+fn plan_instructions<F>(
+    start: Vec<Vec<char>>,
+    target: &[Vec<char>],
+    apply: F,
+    max_yards: usize,
+) -> Result<Vec<(usize, usize, usize)>, PlanError>
+where
+    F: Fn(Vec<Vec<char>>, (usize, usize, usize)) -> Vec<Vec<char>>,
+{
+    let labels = |crates: &[Vec<char>]| crates.iter().flatten().sorted().collect::<String>();
+    if start.len() != target.len() || labels(&start) != labels(target) {
+        return Err(PlanError::Unreachable);
+    }
+
+    // Yards by index with the yard and instruction they come from.
+    let mut previous = vec![None];
+    let mut seen = HashSet::from([start.clone()]);
+    let mut yards = vec![start];
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(index) = queue.pop_front() {
+        if yards[index] == target {
+            let mut program = vec![];
+            let mut index = index;
+            while let Some((before, instruction)) = previous[index] {
+                program.push(instruction);
+                index = before;
+            }
+            program.reverse();
+            return Ok(program);
+        }
+
+        let crates = yards[index].clone();
+        for (from, to) in (1..=crates.len()).cartesian_product(1..=crates.len()) {
+            if from == to {
+                continue;
+            }
+            for mov in 1..=crates[from - 1].len() {
+                let next = apply(crates.clone(), (mov, from, to));
+                if !seen.contains(&next) {
+                    if seen.len() == max_yards {
+                        return Err(PlanError::TooManyYards(max_yards));
+                    }
+                    seen.insert(next.clone());
+                    previous.push(Some((index, (mov, from, to))));
+                    queue.push_back(yards.len());
+                    yards.push(next);
+                }
+            }
+        }
+    }
+
+    Err(PlanError::Unreachable)
+}
+
+/// Executes the instructions as they are read, only the yard is kept in memory.
+fn run_streaming<R: BufRead>(reader: R, insane: bool) -> String {
+    let mut lines = reader.lines().map(Result::unwrap);
+
+    let crates = lines
+        .by_ref()
+        .take_while(|line| !line.starts_with(" 1"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut yard = TreapYard::from(parse_crates(&crates));
+
+    lines
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .map(|line| parse_instruction(&line))
+        .for_each(|instruction| {
+            if insane {
+                yard.apply_insane_instruction(instruction)
+            } else {
+                yard.apply_instruction(instruction)
+            }
+        });
+
+    yard.tops()
+}
+
+/// Stacks as implicit treaps over an arena of crates, in-order from bottom to
+/// top. Taking the top `mov` crates is a split and dropping them on a stack a
+/// merge, both O(log n) expected, so a move costs the same whatever `mov` is.
+/// The one-by-one crane drops the block reversed, which is a lazy flag.
+///
+/// Not O(1) per move: linked stacks splice a block in O(1) but need O(mov) to
+/// find where it starts. O(log n) keeps moves of millions of crates cheap.
+#[derive(Debug)]
+struct TreapYard {
+    labels: Vec<char>,
+    priorities: Vec<u64>,
+    children: Vec<[Option<usize>; 2]>,
+    sizes: Vec<usize>,
+    reversed: Vec<bool>,
+    roots: Vec<Option<usize>>,
+}
+
+impl From<Vec<Vec<char>>> for TreapYard {
+    fn from(crates: Vec<Vec<char>>) -> Self {
+        let mut yard = TreapYard {
+            labels: vec![],
+            priorities: vec![],
+            children: vec![],
+            sizes: vec![],
+            reversed: vec![],
+            roots: vec![None; crates.len()],
+        };
+
+        let mut seed: u64 = 1996;
+        crates.into_iter().enumerate().for_each(|(stack, column)| {
+            column.into_iter().for_each(|label| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+
+                yard.labels.push(label);
+                yard.priorities.push(seed);
+                yard.children.push([None, None]);
+                yard.sizes.push(1);
+                yard.reversed.push(false);
+                let node = Some(yard.labels.len() - 1);
+                yard.roots[stack] = yard.merge(yard.roots[stack], node);
+            })
+        });
+
+        yard
+    }
+}
+
+impl TreapYard {
+    fn apply_instruction(&mut self, (mov, from, to): (usize, usize, usize)) {
+        let from = from - 1;
+        let to = to - 1;
+
+        if from == to {
+            return;
+        }
+
+        let lenght = self.size(self.roots[from]);
+        let (rest, block) = self.split(self.roots[from], lenght - mov.min(lenght));
+        if let Some(block) = block {
+            self.reversed[block] ^= true;
+        }
+        self.roots[from] = rest;
+        self.roots[to] = self.merge(self.roots[to], block);
+    }
+
+    fn apply_insane_instruction(&mut self, (mov, from, to): (usize, usize, usize)) {
+        let from = from - 1;
+        let to = to - 1;
+
+        if mov == 0 || from == to {
+            return;
+        }
+
+        let lenght = self.size(self.roots[from]);
+        let (rest, block) = self.split(self.roots[from], lenght - mov);
+        self.roots[from] = rest;
+        self.roots[to] = self.merge(self.roots[to], block);
+    }
+
+    fn size(&self, node: Option<usize>) -> usize {
+        node.map_or(0, |node| self.sizes[node])
+    }
+
+    /// Applies a pending reversal to the children of `node`.
+    fn push_down(&mut self, node: usize) {
+        if self.reversed[node] {
+            self.children[node].swap(0, 1);
+            self.children[node]
+                .into_iter()
+                .flatten()
+                .for_each(|child| self.reversed[child] ^= true);
+            self.reversed[node] = false;
+        }
+    }
+
+    fn update(&mut self, node: usize) {
+        let [left, right] = self.children[node];
+        self.sizes[node] = 1 + self.size(left) + self.size(right);
+    }
+
+    /// The first `count` crates of `node` and the rest.
+    fn split(&mut self, node: Option<usize>, count: usize) -> (Option<usize>, Option<usize>) {
+        let Some(node) = node else {
+            return (None, None);
+        };
+        self.push_down(node);
+
+        let [left, right] = self.children[node];
+        if self.size(left) >= count {
+            let (first, rest) = self.split(left, count);
+            self.children[node][0] = rest;
+            self.update(node);
+            (first, Some(node))
+        } else {
+            let (first, rest) = self.split(right, count - self.size(left) - 1);
+            self.children[node][1] = first;
+            self.update(node);
+            (Some(node), rest)
+        }
+    }
+
+    fn merge(&mut self, below: Option<usize>, above: Option<usize>) -> Option<usize> {
+        match (below, above) {
+            (None, node) | (node, None) => node,
+            (Some(below), Some(above)) if self.priorities[below] > self.priorities[above] => {
+                self.push_down(below);
+                self.children[below][1] = self.merge(self.children[below][1], Some(above));
+                self.update(below);
+                Some(below)
+            }
+            (Some(below), Some(above)) => {
+                self.push_down(above);
+                self.children[above][0] = self.merge(Some(below), self.children[above][0]);
+                self.update(above);
+                Some(above)
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn stacks(&self) -> Vec<Vec<char>> {
+        self.roots
+            .iter()
+            .map(|&root| {
+                let mut column = vec![];
+                self.collect(root, false, &mut column);
+                column
+            })
+            .collect()
+    }
+
+    /// In-order labels below `node`, `flip` telling whether an ancestor has a
+    /// pending reversal.
+    #[cfg(test)]
+    fn collect(&self, node: Option<usize>, flip: bool, column: &mut Vec<char>) {
+        if let Some(node) = node {
+            let flip = flip ^ self.reversed[node];
+            let [left, right] = self.children[node];
+            let (first, last) = if flip { (right, left) } else { (left, right) };
+
+            self.collect(first, flip, column);
+            column.push(self.labels[node]);
+            self.collect(last, flip, column);
+        }
+    }
+
+    fn tops(&self) -> String {
+        self.roots
+            .iter()
+            .filter_map(|&root| {
+                let mut node = root?;
+                let mut flip = false;
+                loop {
+                    flip ^= self.reversed[node];
+                    match self.children[node][if flip { 0 } else { 1 }] {
+                        Some(child) => node = child,
+                        None => return Some(self.labels[node]),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// A crate remembers where it started, since labels are not unique, and the
+/// (1-based) instructions that moved it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrackedCrate {
+    label: char,
+    stack: usize,
+    height: usize,
+    moves: Vec<usize>,
+}
+
+fn track_crates(crates: &[Vec<char>]) -> Vec<Vec<TrackedCrate>> {
+    crates
+        .iter()
+        .enumerate()
+        .map(|(stack, column)| {
+            column
+                .iter()
+                .enumerate()
+                .map(|(height, &label)| TrackedCrate {
+                    label,
+                    stack: stack + 1,
+                    height: height + 1,
+                    moves: vec![],
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn simulate_tracked<F>(
+    crates: Vec<Vec<TrackedCrate>>,
+    instructions: &[(usize, usize, usize)],
+    apply: F,
+) -> Vec<Vec<TrackedCrate>>
+where
+    F: Fn(Vec<Vec<TrackedCrate>>, (usize, usize, usize)) -> Vec<Vec<TrackedCrate>>,
+{
+    instructions
+        .iter()
+        .copied()
+        .enumerate()
+        .fold(crates, |crates, (index, instruction)| {
+            let (_, _, to) = instruction;
+            let untouched = crates[to - 1].len();
+
+            let mut crates = apply(crates, instruction);
+            crates[to - 1][untouched..]
+                .iter_mut()
+                .for_each(|moved| moved.moves.push(index + 1));
+            crates
+        })
+}
+
 #[cfg(test)]
 mod test {
     use crate::day5::{
-        apply_insane_instruction, apply_instruction, parse_crates_row, parse_instruction,
+        apply_insane_instruction, apply_instruction, equivalent_programs, merge_insane_instruction,
+        merge_instruction, optimize_program, parse_crates_row, parse_instruction, reverse_solve,
+        simulate_tracked, track_crates, unapply_insane_instruction, unapply_instruction,
+        TrackedCrate,
+    };
+    use std::io::Cursor;
+
+    use super::{
+        format_instruction, format_yard, parse_yard, plan_instructions, run_streaming, PlanError,
+        TreapYard,
     };
 
     use super::{parse_crates, split_input};
@@ -411,6 +1116,364 @@ move 6 from 6 to 5",
             ]
         );
     }
+
+    #[test]
+    fn unapply_instruction_bim() {
+        assert_eq!(
+            unapply_instruction(vec![vec![], vec!['L', 'C', 'L', 'W']], (2, 1, 2)),
+            Some(vec![vec!['W', 'L'], vec!['L', 'C']])
+        );
+        assert_eq!(
+            unapply_instruction(vec![vec![], vec!['L']], (2, 1, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn unapply_insane_instruction_bim() {
+        assert_eq!(
+            unapply_insane_instruction(vec![vec![], vec!['L', 'C', 'W', 'L']], (2, 1, 2)),
+            Some(vec![vec!['W', 'L'], vec!['L', 'C']])
+        );
+        assert_eq!(
+            unapply_insane_instruction(vec![vec![], vec!['L']], (2, 1, 2)),
+            None
+        );
+    }
+
+    #[test]
+    fn reverse_solve_boum() {
+        let crates = vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']];
+        let instructions = vec![(1, 2, 1), (3, 1, 3), (2, 2, 1), (1, 1, 2)];
+
+        let final_crates = instructions
+            .iter()
+            .copied()
+            .fold(crates.clone(), apply_instruction);
+        assert_eq!(
+            reverse_solve(final_crates, &instructions, unapply_instruction),
+            Some(crates.clone())
+        );
+
+        let final_crates = instructions
+            .iter()
+            .copied()
+            .fold(crates.clone(), apply_insane_instruction);
+        assert_eq!(
+            reverse_solve(final_crates, &instructions, unapply_insane_instruction),
+            Some(crates)
+        );
+
+        assert_eq!(
+            reverse_solve(
+                vec![vec![], vec![], vec![]],
+                &instructions,
+                unapply_instruction
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn merge_instruction_bim() {
+        assert_eq!(merge_instruction((2, 1, 2), (3, 1, 2)), Some((5, 1, 2)));
+        assert_eq!(merge_instruction((2, 1, 2), (3, 2, 1)), Some((1, 2, 1)));
+        assert_eq!(merge_instruction((3, 1, 2), (3, 2, 1)), Some((0, 1, 2)));
+        assert_eq!(merge_instruction((1, 1, 2), (1, 2, 3)), Some((1, 1, 3)));
+        assert_eq!(merge_instruction((2, 1, 2), (2, 2, 3)), None);
+        assert_eq!(merge_instruction((2, 1, 2), (2, 3, 1)), None);
+    }
+
+    #[test]
+    fn merge_insane_instruction_bim() {
+        assert_eq!(
+            merge_insane_instruction((2, 1, 2), (2, 2, 3)),
+            Some((2, 1, 3))
+        );
+        assert_eq!(
+            merge_insane_instruction((2, 1, 2), (2, 2, 1)),
+            Some((2, 1, 1))
+        );
+        assert_eq!(merge_insane_instruction((2, 1, 2), (3, 1, 2)), None);
+        assert_eq!(merge_insane_instruction((2, 1, 2), (1, 2, 3)), None);
+    }
+
+    #[test]
+    fn optimize_program_boum() {
+        let crates = vec![vec!['Z', 'N', 'A', 'B'], vec!['M', 'C', 'D'], vec!['P']];
+        let instructions = vec![
+            (1, 2, 1),
+            (0, 1, 3),
+            (2, 1, 3),
+            (2, 3, 1),
+            (2, 2, 2),
+            (1, 1, 2),
+            (1, 2, 3),
+        ];
+
+        let optimized = optimize_program(&crates, &instructions, merge_instruction);
+        assert_eq!(optimized, vec![(1, 2, 3)]);
+        assert!(equivalent_programs(
+            &crates,
+            &instructions,
+            &optimized,
+            apply_instruction
+        ));
+
+        let optimized = optimize_program(&crates, &instructions, merge_insane_instruction);
+        assert_eq!(optimized, vec![(1, 2, 3)]);
+        assert!(equivalent_programs(
+            &crates,
+            &instructions,
+            &optimized,
+            apply_insane_instruction
+        ));
+
+        let instructions = vec![(2, 1, 2), (2, 2, 3)];
+        assert_eq!(
+            optimize_program(&crates, &instructions, merge_instruction),
+            instructions
+        );
+        assert_eq!(
+            optimize_program(&crates, &instructions, merge_insane_instruction),
+            vec![(2, 1, 3)]
+        );
+
+        let crates = vec![vec!['A'], vec!['B', 'C'], vec![]];
+        for instructions in [vec![(2, 1, 2), (2, 2, 1)], vec![(1, 3, 1), (1, 1, 2)]] {
+            let optimized = optimize_program(&crates, &instructions, merge_instruction);
+            assert_eq!(optimized, instructions);
+            assert!(equivalent_programs(
+                &crates,
+                &instructions,
+                &optimized,
+                apply_instruction
+            ));
+        }
+
+        let instructions = vec![(1, 1, 2), (2, 2, 1), (1, 1, 3), (1, 3, 2)];
+        let optimized = optimize_program(&crates, &instructions, merge_instruction);
+        assert_eq!(optimized, vec![]);
+        assert!(equivalent_programs(
+            &crates,
+            &instructions,
+            &optimized,
+            apply_instruction
+        ));
+    }
+
+    #[test]
+    fn track_crates_bim() {
+        assert_eq!(
+            track_crates(&[vec!['A', 'A'], vec![]]),
+            vec![
+                vec![
+                    TrackedCrate {
+                        label: 'A',
+                        stack: 1,
+                        height: 1,
+                        moves: vec![]
+                    },
+                    TrackedCrate {
+                        label: 'A',
+                        stack: 1,
+                        height: 2,
+                        moves: vec![]
+                    }
+                ],
+                vec![]
+            ]
+        );
+    }
+
+    #[test]
+    fn simulate_tracked_boum() {
+        let crates = track_crates(&[vec!['A', 'B', 'A'], vec!['C']]);
+        let instructions = vec![(2, 1, 2), (1, 2, 1)];
+
+        let origins = |crates: Vec<Vec<TrackedCrate>>| {
+            crates
+                .into_iter()
+                .map(|column| {
+                    column
+                        .into_iter()
+                        .map(|tracked| {
+                            (tracked.label, tracked.stack, tracked.height, tracked.moves)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            origins(simulate_tracked(
+                crates.clone(),
+                &instructions,
+                apply_instruction
+            )),
+            vec![
+                vec![('A', 1, 1, vec![]), ('B', 1, 2, vec![1, 2])],
+                vec![('C', 2, 1, vec![]), ('A', 1, 3, vec![1])],
+            ]
+        );
+        assert_eq!(
+            origins(simulate_tracked(
+                crates,
+                &instructions,
+                apply_insane_instruction
+            )),
+            vec![
+                vec![('A', 1, 1, vec![]), ('A', 1, 3, vec![1, 2])],
+                vec![('C', 2, 1, vec![]), ('B', 1, 2, vec![1])],
+            ]
+        );
+    }
+
+    #[test]
+    fn treap_yard_bim() {
+        let crates = vec![vec!['W', 'L', 'A'], vec!['L', 'C'], vec![]];
+        assert_eq!(TreapYard::from(crates.clone()).stacks(), crates);
+        assert_eq!(TreapYard::from(crates.clone()).tops(), "AC");
+
+        for instruction in [(1, 1, 2), (2, 1, 3), (3, 1, 2), (2, 2, 2), (0, 2, 1)] {
+            let mut yard = TreapYard::from(crates.clone());
+            yard.apply_instruction(instruction);
+            assert_eq!(
+                yard.stacks(),
+                apply_instruction(crates.clone(), instruction)
+            );
+
+            let mut yard = TreapYard::from(crates.clone());
+            yard.apply_insane_instruction(instruction);
+            assert_eq!(
+                yard.stacks(),
+                apply_insane_instruction(crates.clone(), instruction)
+            );
+        }
+
+        let crates = vec![
+            "ABCDEFGHIJ".chars().collect(),
+            "KLMNO".chars().collect(),
+            vec![],
+        ];
+        let instructions = [
+            (4, 1, 3),
+            (2, 3, 2),
+            (7, 2, 1),
+            (3, 1, 2),
+            (1, 3, 1),
+            (7, 1, 3),
+        ];
+        let (mut yard, mut insane_yard) = (
+            TreapYard::from(crates.clone()),
+            TreapYard::from(crates.clone()),
+        );
+        let (mut expected, mut insane_expected) = (crates.clone(), crates);
+        for instruction in instructions {
+            yard.apply_instruction(instruction);
+            expected = apply_instruction(expected, instruction);
+            assert_eq!(yard.stacks(), expected);
+            assert_eq!(
+                yard.tops(),
+                expected
+                    .iter()
+                    .filter_map(|column| column.last())
+                    .collect::<String>()
+            );
+
+            insane_yard.apply_insane_instruction(instruction);
+            insane_expected = apply_insane_instruction(insane_expected, instruction);
+            assert_eq!(insane_yard.stacks(), insane_expected);
+        }
+    }
+
+    #[test]
+    fn run_streaming_boum() {
+        let input = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n\nmove 1 from 2 to 1\nmove 3 from 1 to 3\nmove 2 from 2 to 1\nmove 1 from 1 to 2\n";
+
+        assert_eq!(run_streaming(Cursor::new(input), false), "CMZ");
+        assert_eq!(run_streaming(Cursor::new(input), true), "MCD");
+    }
+
+    #[test]
+    fn parse_yard_bim() {
+        assert_eq!(
+            parse_yard("    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n"),
+            vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']]
+        );
+        assert_eq!(
+            parse_yard("    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3"),
+            vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']]
+        );
+        assert_eq!(
+            parse_yard("[C]\n[M] [P]\n 1 2 3"),
+            vec![vec!['M', 'C'], vec!['P'], vec![]]
+        );
+    }
+
+    #[test]
+    fn format_yard_boum() {
+        let yard = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n";
+        let crates = vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']];
+
+        assert_eq!(format_yard(&crates), yard);
+        assert_eq!(parse_yard(&format_yard(&crates)), crates);
+        assert_eq!(format_yard(&[vec![], vec!['A']]), "    [A]\n 1   2 \n");
+    }
+
+    #[test]
+    fn format_instruction_bam() {
+        assert_eq!(parse_instruction(&format_instruction((4, 3, 7))), (4, 3, 7));
+        assert_eq!(format_instruction((4, 3, 7)), "move 4 from 3 to 7");
+    }
+
+    #[test]
+    fn plan_instructions_boum() {
+        let start = vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']];
+
+        let target = vec![vec!['C'], vec!['M'], vec!['P', 'D', 'N', 'Z']];
+        let program =
+            plan_instructions(start.clone(), &target, apply_instruction, 100_000).unwrap();
+        assert_eq!(program.len(), 3);
+        assert_eq!(
+            program.into_iter().fold(start.clone(), apply_instruction),
+            target
+        );
+
+        let target = vec![vec!['M'], vec!['C'], vec!['P', 'Z', 'N', 'D']];
+        let program =
+            plan_instructions(start.clone(), &target, apply_insane_instruction, 100_000).unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(
+            program
+                .into_iter()
+                .fold(start.clone(), apply_insane_instruction),
+            target
+        );
+
+        assert_eq!(
+            plan_instructions(start.clone(), &start, apply_instruction, 1),
+            Ok(vec![])
+        );
+        assert_eq!(
+            plan_instructions(
+                start.clone(),
+                &[vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['Q']],
+                apply_instruction,
+                1000
+            ),
+            Err(PlanError::Unreachable)
+        );
+        assert_eq!(
+            plan_instructions(
+                start,
+                &[vec!['N', 'Z'], vec!['D', 'C', 'M'], vec!['P']],
+                apply_instruction,
+                10
+            ),
+            Err(PlanError::TooManyYards(10))
+        );
+    }
 }
 
 fn input() -> String {
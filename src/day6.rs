@@ -1,46 +1,469 @@
-use itertools::FoldWhile::{Continue, Done};
-use itertools::Itertools;
-use std::fs;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Instant,
+};
 
 pub fn part1() {
-    let answer = find_marker(&input(), 4);
+    part1_with(Mode::Bytes)
+}
+pub fn part1_with(mode: Mode) {
+    let answer = mode.find_marker(&input(), 4);
     println!("day5, part1: {:?}", answer);
 }
 
 pub fn part2() {
-    let answer = find_marker(&input(), 14);
+    part2_with(Mode::Bytes)
+}
+pub fn part2_with(mode: Mode) {
+    let answer = mode.find_marker(&input(), 14);
 
     println!("day5, part2: {:?}", answer);
 }
 
+pub fn both_parts() {
+    let answer = first_markers(input().bytes(), &[4, 14]);
+
+    println!("day6, both parts: {:?}", answer);
+}
+
+pub fn stream(mode: Mode, lenght: usize, path: Option<&str>) {
+    let answer = match path {
+        Some(path) => mode.find_marker_in(File::open(path).unwrap(), lenght),
+        None => mode.find_marker_in(io::stdin().lock(), lenght),
+    }
+    .unwrap();
+
+    println!("day6, stream {}: {:?}", lenght, answer);
+}
+
+pub fn parallel(lenght: usize, threads: usize, path: Option<&str>) {
+    let input = fs::read(path.unwrap_or("day6")).unwrap();
+    let answer = find_marker_parallel(&input, lenght, threads);
+
+    println!("day6, parallel {}: {:?}", lenght, answer);
+}
+
+pub fn decode() {
+    Decoder::default()
+        .decode(input().as_bytes())
+        .into_iter()
+        .for_each(|frame| {
+            println!(
+                "day6, decode: {:?} {:?} {:?}",
+                frame.kind,
+                frame.marker,
+                String::from_utf8_lossy(&frame.data)
+            )
+        });
+}
+
+pub fn markers(overlapping: bool) {
+    count_markers(&input(), 1..=14, overlapping)
+        .into_iter()
+        .for_each(|(lenght, count)| println!("day6, markers {}: {}", lenght, count));
+}
+
+/// Scans a stream of `size` bytes where no marker can be found, so the whole
+/// stream is read. The bytes are generated as they are scanned, so `size` can
+/// go well past the RAM.
+pub fn bench(size: usize) {
+    for lenght in [4, 14] {
+        let stream = (b'a'..b'a' + lenght as u8 - 1).cycle().take(size);
+
+        let start = Instant::now();
+        let answer = find_marker_in_bytes(stream, lenght);
+        let elapsed = start.elapsed();
+
+        println!(
+            "day6, bench {}: {} bytes, marker {:?}, in {:?} ({:.0} MB/s)",
+            lenght,
+            size,
+            answer,
+            elapsed,
+            size as f64 / elapsed.as_secs_f64() / 1_000_000.0
+        );
+    }
+}
+
+/// How markers are searched, `--chars` on the command line picks `Chars`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Bytes,
+    Chars,
+}
+
+impl Mode {
+    pub fn from_args(args: &[&str]) -> Self {
+        match args {
+            [] => Mode::Bytes,
+            ["--chars"] => Mode::Chars,
+            _ => panic!("unknown flags {:?}", args),
+        }
+    }
+
+    fn find_marker(self, input: &str, lenght: usize) -> (usize, String) {
+        match self {
+            Mode::Bytes => find_marker(input, lenght),
+            Mode::Chars => find_marker_chars(input, lenght),
+        }
+    }
+
+    /// Bytes are scanned as they are read, chars need the whole UTF-8 input.
+    fn find_marker_in<R: Read>(
+        self,
+        mut reader: R,
+        lenght: usize,
+    ) -> io::Result<Option<(usize, String)>> {
+        match self {
+            Mode::Bytes => find_marker_in(reader, lenght),
+            Mode::Chars => {
+                let mut input = String::new();
+                reader.read_to_string(&mut input)?;
+                let (position, marker) = find_marker_chars(&input, lenght);
+                Ok((marker.chars().count() == lenght).then_some((position, marker)))
+            }
+        }
+    }
+}
+
+/// Byte mode, fast but only meaningful for ASCII signals: positions count bytes
+/// and a multibyte character is seen as several distinct bytes.
 fn find_marker(input: &str, lenght: usize) -> (usize, String) {
+    let mut detector = MarkerDetector::new(lenght);
+
     input
-        .chars()
-        .fold_while((0, "".to_string()), |(index, mut potential_marker), c| {
-            let index = index + 1;
-            let potential_marker = if let Some(index_of) = potential_marker.find(c) {
-                format!(
-                    "{}{}",
-                    potential_marker.drain((index_of + 1)..).collect::<String>(),
-                    c
-                )
-            } else {
-                format!("{}{}", potential_marker, c)
+        .bytes()
+        .find_map(|byte| detector.push(byte))
+        .unwrap_or_else(|| (input.len(), detector.window()))
+}
+
+/// Char mode, correct for any UTF-8 stream: positions count chars. Each char
+/// remembers the position and byte offset following its last occurrence.
+fn find_marker_chars(input: &str, lenght: usize) -> (usize, String) {
+    let mut last_seen: HashMap<char, (usize, usize)> = HashMap::new();
+    let (mut start, mut start_offset) = (0, 0);
+    let mut position = 0;
+
+    for (offset, c) in input.char_indices() {
+        if let Some(&(seen, seen_offset)) = last_seen.get(&c) {
+            if seen > start {
+                start = seen;
+                start_offset = seen_offset;
+            }
+        }
+
+        position += 1;
+        let end_offset = offset + c.len_utf8();
+        last_seen.insert(c, (position, end_offset));
+
+        if position - start == lenght {
+            return (position, input[start_offset..end_offset].to_string());
+        }
+    }
+
+    (position, input[start_offset..].to_string())
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameKind {
+    Packet,
+    Message,
+}
+
+/// A frame starts with its marker and its payload runs until the next marker.
+/// Ranges are byte offsets, markers being searched in byte mode.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub marker: Range<usize>,
+    pub payload: Range<usize>,
+    pub data: Vec<u8>,
+}
+
+/// Splits a datastream into alternating packets and messages: a start-of-packet
+/// marker, then a start-of-message marker searched after it, and so on.
+#[derive(Debug)]
+pub struct Decoder {
+    pub packet_lenght: usize,
+    pub message_lenght: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            packet_lenght: 4,
+            message_lenght: 14,
+        }
+    }
+}
+
+impl Decoder {
+    pub fn decode(&self, input: &[u8]) -> Vec<Frame> {
+        let mut frames: Vec<Frame> = vec![];
+        let mut kinds = [FrameKind::Packet, FrameKind::Message].into_iter().cycle();
+        let mut position = 0;
+
+        loop {
+            let kind = kinds.next().unwrap();
+            let lenght = match kind {
+                FrameKind::Packet => self.packet_lenght,
+                FrameKind::Message => self.message_lenght,
             };
 
-            if potential_marker.len() == lenght {
-                Done((index, potential_marker))
-            } else {
-                Continue((index, potential_marker))
+            let found = find_marker_in_bytes(input[position..].iter().copied(), lenght)
+                .map(|(index, _)| position + index);
+
+            if let Some(previous) = frames.last_mut() {
+                previous.payload.end = found.map_or(input.len(), |end| end - lenght);
+                previous.data = input[previous.payload.clone()].to_vec();
             }
+            let Some(end) = found else {
+                return frames;
+            };
+
+            frames.push(Frame {
+                kind,
+                marker: (end - lenght)..end,
+                payload: end..end,
+                data: vec![],
+            });
+            position = end;
+        }
+    }
+}
+
+/// Reads the source incrementally and stops as soon as the marker is found.
+fn find_marker_in<R: Read>(reader: R, lenght: usize) -> io::Result<Option<(usize, String)>> {
+    let mut detector = MarkerDetector::new(lenght);
+
+    for byte in BufReader::new(reader).bytes() {
+        if let Some(marker) = detector.push(byte?) {
+            return Ok(Some(marker));
+        }
+    }
+
+    Ok(None)
+}
+
+fn find_marker_in_bytes<I>(bytes: I, lenght: usize) -> Option<(usize, String)>
+where
+    I: IntoIterator<Item = u8>,
+{
+    let mut detector = MarkerDetector::new(lenght);
+
+    bytes.into_iter().find_map(|byte| detector.push(byte))
+}
+
+/// Each thread scans its own chunk, starting `lenght - 1` bytes early so that
+/// markers across a boundary are found by the chunk where they end. Chunks
+/// after the first one holding a marker give up as soon as they notice it.
+fn find_marker_parallel(input: &[u8], lenght: usize, threads: usize) -> Option<(usize, String)> {
+    let chunk_size = input.len().div_ceil(threads.max(1)).max(1);
+    let first_found = AtomicUsize::new(usize::MAX);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..input.len())
+            .step_by(chunk_size)
+            .enumerate()
+            .map(|(chunk, start)| {
+                let first_found = &first_found;
+                scope.spawn(move || {
+                    let overlap = start.saturating_sub(lenght.saturating_sub(1));
+                    let end = (start + chunk_size).min(input.len());
+                    let mut detector = MarkerDetector::new(lenght);
+
+                    for bytes in input[overlap..end].chunks(4096) {
+                        if first_found.load(Ordering::Relaxed) < chunk {
+                            return None;
+                        }
+                        if let Some((index, marker)) =
+                            bytes.iter().find_map(|&byte| detector.push(byte))
+                        {
+                            first_found.fetch_min(chunk, Ordering::Relaxed);
+                            return Some((overlap + index, marker));
+                        }
+                    }
+                    None
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().unwrap())
+            .next()
+    })
+}
+
+/// Every marker of the stream, either each position ending `lenght` distinct
+/// bytes (`overlapping`) or starting afresh after each marker found.
+fn find_markers<I>(bytes: I, lenght: usize, overlapping: bool) -> Markers<I::IntoIter>
+where
+    I: IntoIterator<Item = u8>,
+{
+    Markers {
+        bytes: bytes.into_iter(),
+        detector: MarkerDetector::new(lenght),
+        overlapping,
+    }
+}
+
+fn count_markers<L>(input: &str, lenghts: L, overlapping: bool) -> Vec<(usize, usize)>
+where
+    L: IntoIterator<Item = usize>,
+{
+    lenghts
+        .into_iter()
+        .map(|lenght| {
+            let count = find_markers(input.bytes(), lenght, overlapping).count();
+            (lenght, count)
         })
-        .into_inner()
+        .collect()
+}
+
+struct Markers<I> {
+    bytes: I,
+    detector: MarkerDetector,
+    overlapping: bool,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Markers<I> {
+    type Item = (usize, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let marker = self.bytes.find_map(|byte| self.detector.push(byte))?;
+        if !self.overlapping {
+            self.detector.reset();
+        }
+        Some(marker)
+    }
+}
+
+/// Length of the longest run of distinct bytes ending at each position, a
+/// marker of any length `n` ends wherever the run is at least `n`.
+fn distinct_runs<I>(bytes: I) -> DistinctRuns<I::IntoIter>
+where
+    I: IntoIterator<Item = u8>,
+{
+    DistinctRuns {
+        bytes: bytes.into_iter(),
+        position: 0,
+        start: 0,
+        last_seen: [0; 256],
+    }
+}
+
+/// First marker end position for each of the lengths, in a single pass that
+/// stops once they are all found.
+fn first_markers<I>(bytes: I, lenghts: &[usize]) -> Vec<(usize, Option<usize>)>
+where
+    I: IntoIterator<Item = u8>,
+{
+    let mut found: Vec<(usize, Option<usize>)> =
+        lenghts.iter().map(|&lenght| (lenght, None)).collect();
+    let mut missing = found.len();
+
+    for (index, run) in distinct_runs(bytes).enumerate() {
+        if missing == 0 {
+            break;
+        }
+        found
+            .iter_mut()
+            .filter(|(lenght, position)| position.is_none() && *lenght <= run)
+            .for_each(|(_, position)| {
+                *position = Some(index + 1);
+                missing -= 1;
+            });
+    }
+
+    found
+}
+
+struct DistinctRuns<I> {
+    bytes: I,
+    position: usize,
+    start: usize,
+    last_seen: [usize; 256],
+}
+
+impl<I: Iterator<Item = u8>> Iterator for DistinctRuns<I> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = self.bytes.next()?;
+
+        self.start = self.start.max(self.last_seen[byte as usize]);
+        self.position += 1;
+        self.last_seen[byte as usize] = self.position;
+
+        Some(self.position - self.start)
+    }
+}
+
+/// Sliding window over the bytes: `last_seen` keeps the position following the
+/// last occurrence of each byte, so a repeated byte moves the window start past
+/// it in constant time.
+struct MarkerDetector {
+    lenght: usize,
+    position: usize,
+    last_seen: [usize; 256],
+    window: VecDeque<u8>,
+}
+
+impl MarkerDetector {
+    fn new(lenght: usize) -> Self {
+        MarkerDetector {
+            lenght,
+            position: 0,
+            last_seen: [0; 256],
+            window: VecDeque::with_capacity(lenght),
+        }
+    }
+
+    /// Feeds the next byte, returning the marker when this byte completes it.
+    fn push(&mut self, byte: u8) -> Option<(usize, String)> {
+        let start = self.position - self.window.len();
+        let repeated = self.last_seen[byte as usize].saturating_sub(start);
+        self.window.drain(..repeated);
+        if self.window.len() == self.lenght {
+            self.window.pop_front();
+        }
+
+        self.position += 1;
+        self.last_seen[byte as usize] = self.position;
+        self.window.push_back(byte);
+
+        if self.window.len() == self.lenght {
+            Some((self.position, self.window()))
+        } else {
+            None
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+
+    fn window(&self) -> String {
+        String::from_utf8_lossy(&self.window.iter().copied().collect::<Vec<_>>()).into_owned()
+    }
 }
 
 #[cfg(test)]
 mod test {
 
-    use super::find_marker;
+    use std::io::Cursor;
+
+    use super::{
+        count_markers, distinct_runs, find_marker, find_marker_chars, find_marker_in,
+        find_marker_in_bytes, find_marker_parallel, find_markers, first_markers, Decoder, Frame,
+        FrameKind, MarkerDetector, Mode,
+    };
 
     #[test]
     fn find_marker_bim() {
@@ -52,6 +475,237 @@ mod test {
         assert_eq!(find_marker("srlsrsnnwh", 4), (10, "nwh".to_string()));
         assert_eq!(find_marker("azer", 2), (2, "az".to_string()));
     }
+
+    #[test]
+    fn find_marker_boum() {
+        assert_eq!(
+            find_marker("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 4),
+            (7, "jpqm".to_string())
+        );
+        assert_eq!(
+            find_marker("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 14),
+            (19, "qmgbljsphdztnv".to_string())
+        );
+        assert_eq!(
+            find_marker("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 14),
+            (26, "jwzlrfnpqdbhtm".to_string())
+        );
+        assert_eq!(find_marker("", 4), (0, "".to_string()));
+    }
+
+    #[test]
+    fn find_marker_bytes_bam() {
+        assert_eq!(find_marker("ééa", 2), (2, "é".to_string()));
+        assert_eq!(find_marker("ééa", 3), (5, "éa".to_string()));
+        assert_eq!(find_marker("éè", 3), (4, "\u{FFFD}è".to_string()));
+        assert_eq!(find_marker("éé", 3), (4, "é".to_string()));
+    }
+
+    #[test]
+    fn find_marker_chars_bam() {
+        assert_eq!(find_marker_chars("azeazerqsdf", 4), (7, "azer".to_string()));
+        assert_eq!(find_marker_chars("srlsrsnnwh", 4), (10, "nwh".to_string()));
+        assert_eq!(find_marker_chars("ééa", 2), (3, "éa".to_string()));
+        assert_eq!(find_marker_chars("éàéüa", 3), (4, "àéü".to_string()));
+        assert_eq!(
+            find_marker_chars("日本日本語", 3),
+            (5, "日本語".to_string())
+        );
+        assert_eq!(find_marker_chars("éé", 3), (2, "é".to_string()));
+    }
+
+    #[test]
+    fn mode_bim() {
+        assert_eq!(Mode::from_args(&[]), Mode::Bytes);
+        assert_eq!(Mode::from_args(&["--chars"]), Mode::Chars);
+        assert_eq!(Mode::Bytes.find_marker("ééa", 3), (5, "éa".to_string()));
+        assert_eq!(Mode::Chars.find_marker("ééa", 2), (3, "éa".to_string()));
+
+        assert_eq!(
+            Mode::Bytes.find_marker_in(Cursor::new("éàéüa"), 3).unwrap(),
+            Some((4, "\u{FFFD}à".to_string()))
+        );
+        assert_eq!(
+            Mode::Chars.find_marker_in(Cursor::new("éàéüa"), 3).unwrap(),
+            Some((4, "àéü".to_string()))
+        );
+        assert_eq!(
+            Mode::Chars.find_marker_in(Cursor::new("éé"), 3).unwrap(),
+            None
+        );
+        assert!(Mode::Chars
+            .find_marker_in(Cursor::new([0xff, b'a', b'b']), 2)
+            .is_err());
+    }
+
+    #[test]
+    fn find_marker_parallel_bim() {
+        let input = "mjqjpqmgbljsphdztnvjfqwrcgsmlb".as_bytes();
+
+        for threads in [1, 2, 3, 7, 30, 64] {
+            assert_eq!(
+                find_marker_parallel(input, 4, threads),
+                Some((7, "jpqm".to_string()))
+            );
+            assert_eq!(
+                find_marker_parallel(input, 14, threads),
+                Some((19, "qmgbljsphdztnv".to_string()))
+            );
+            assert_eq!(find_marker_parallel(b"srlsrsnnwh", 4, threads), None);
+        }
+        assert_eq!(find_marker_parallel(b"", 4, 4), None);
+    }
+
+    #[test]
+    fn decoder_bim() {
+        assert_eq!(
+            Decoder::default().decode(b"mjqjpqmgbljsphdztnvjfqwrcgsmlb"),
+            vec![
+                Frame {
+                    kind: FrameKind::Packet,
+                    marker: 3..7,
+                    payload: 7..11,
+                    data: b"gblj".to_vec()
+                },
+                Frame {
+                    kind: FrameKind::Message,
+                    marker: 11..25,
+                    payload: 25..25,
+                    data: vec![]
+                },
+                Frame {
+                    kind: FrameKind::Packet,
+                    marker: 25..29,
+                    payload: 29..30,
+                    data: b"b".to_vec()
+                }
+            ]
+        );
+
+        let decoder = Decoder {
+            packet_lenght: 2,
+            message_lenght: 3,
+        };
+        assert_eq!(
+            decoder
+                .decode(b"aabccaabcdd")
+                .into_iter()
+                .map(|frame| (frame.kind, frame.marker, frame.data))
+                .collect::<Vec<_>>(),
+            vec![
+                (FrameKind::Packet, 1..3, b"cca".to_vec()),
+                (FrameKind::Message, 6..9, b"dd".to_vec()),
+            ]
+        );
+        assert_eq!(Decoder::default().decode(b"aaaa"), vec![]);
+
+        let frames = Decoder::default().decode("a😀bcdefghijklmnopqrstuvwxyz".as_bytes());
+        assert_eq!(
+            frames
+                .iter()
+                .map(|frame| (frame.kind, frame.marker.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (FrameKind::Packet, 0..4),
+                (FrameKind::Message, 4..18),
+                (FrameKind::Packet, 18..22),
+            ]
+        );
+        assert_eq!(frames[2].data, b"stuvwxyz".to_vec());
+
+        assert_eq!(
+            decoder.decode("aéb".as_bytes()),
+            vec![Frame {
+                kind: FrameKind::Packet,
+                marker: 0..2,
+                payload: 2..4,
+                data: vec![0xa9, b'b']
+            }]
+        );
+    }
+
+    #[test]
+    fn marker_detector_bim() {
+        let mut detector = MarkerDetector::new(4);
+        assert_eq!(detector.push(b'a'), None);
+        assert_eq!(detector.push(b'z'), None);
+        assert_eq!(detector.push(b'a'), None);
+        assert_eq!(detector.window(), "za");
+        assert_eq!(detector.push(b'e'), None);
+        assert_eq!(detector.push(b'r'), Some((5, "zaer".to_string())));
+        assert_eq!(detector.push(b't'), Some((6, "aert".to_string())));
+        assert_eq!(detector.push(b't'), None);
+        assert_eq!(detector.window(), "t");
+    }
+
+    #[test]
+    fn find_marker_in_boum() {
+        assert_eq!(
+            find_marker_in(Cursor::new("mjqjpqmgbljsphdztnvjfqwrcgsmlb"), 4).unwrap(),
+            Some((7, "jpqm".to_string()))
+        );
+        assert_eq!(find_marker_in(Cursor::new("srlsrsnnwh"), 4).unwrap(), None);
+
+        let endless = "mjqjpqmgbljsphdztnvjfqwrcgsmlb"
+            .bytes()
+            .chain(std::iter::repeat(b'a'));
+        assert_eq!(
+            find_marker_in_bytes(endless, 14),
+            Some((19, "qmgbljsphdztnv".to_string()))
+        );
+    }
+
+    #[test]
+    fn find_markers_bim() {
+        assert_eq!(
+            find_markers("azerazzer".bytes(), 3, true).collect::<Vec<_>>(),
+            vec![
+                (3, "aze".to_string()),
+                (4, "zer".to_string()),
+                (5, "era".to_string()),
+                (6, "raz".to_string()),
+                (9, "zer".to_string())
+            ]
+        );
+        assert_eq!(
+            find_markers("azerazzer".bytes(), 3, false).collect::<Vec<_>>(),
+            vec![
+                (3, "aze".to_string()),
+                (6, "raz".to_string()),
+                (9, "zer".to_string())
+            ]
+        );
+        assert_eq!(find_markers("aaaa".bytes(), 2, true).count(), 0);
+    }
+
+    #[test]
+    fn distinct_runs_bim() {
+        assert_eq!(
+            distinct_runs("azerazzer".bytes()).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 4, 4, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn first_markers_bam() {
+        assert_eq!(
+            first_markers("mjqjpqmgbljsphdztnvjfqwrcgsmlb".bytes(), &[4, 14, 1, 27]),
+            vec![(4, Some(7)), (14, Some(19)), (1, Some(1)), (27, None)]
+        );
+        assert_eq!(first_markers("".bytes(), &[4]), vec![(4, None)]);
+    }
+
+    #[test]
+    fn count_markers_boum() {
+        assert_eq!(
+            count_markers("azerazzer", 1..=4, true),
+            vec![(1, 9), (2, 7), (3, 5), (4, 3)]
+        );
+        assert_eq!(
+            count_markers("azerazzer", [2, 4], false),
+            vec![(2, 4), (4, 1)]
+        );
+    }
 }
 
 fn input() -> String {
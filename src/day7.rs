@@ -1,341 +1,2189 @@
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::{cmp::Ordering, collections::HashMap, fs, slice::SliceIndex};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process,
+};
 
 lazy_static! {
-    static ref CD_REGEX: Regex = Regex::new(r"\$ cd (.+)").unwrap();
-    static ref LS_REGEX: Regex = Regex::new(r"\$ ls").unwrap();
-    static ref DIR_REGEX: Regex = Regex::new(r"dir (.+)").unwrap();
-    static ref FILE_REGEX: Regex = Regex::new(r"(\d+) (.+)").unwrap();
-    static ref PREVIOUS_DIR_REGEX: Regex = Regex::new(r"(.+)/.+").unwrap();
+    static ref CD_REGEX: Regex = Regex::new(r"^\$ cd (.+)$").unwrap();
+    static ref LS_REGEX: Regex = Regex::new(r"^\$ ls(?: (.+))?$").unwrap();
+    static ref PWD_REGEX: Regex = Regex::new(r"^\$ pwd$").unwrap();
+    static ref RM_REGEX: Regex = Regex::new(r"^\$ rm (?:-\w+ )*([^-].*)$").unwrap();
+    static ref DIR_REGEX: Regex = Regex::new(r"^dir (.+)$").unwrap();
+    static ref FILE_REGEX: Regex = Regex::new(r"^(\d+) (.+)$").unwrap();
 }
 
 pub fn part1() -> usize {
-    part1_run(&input())
+    part1_run(&input(), &DiskConfig::default())
 }
-fn part1_run(input: &str) -> usize {
-    let resulting_line: Vec<(String, Line)> = Vec::new();
-    let (_, resulting_line) = input
-        .split('\n')
+pub fn part1_with(config: &DiskConfig) -> usize {
+    part1_run(&input(), config)
+}
+fn part1_run(input: &str, config: &DiskConfig) -> usize {
+    let file_system = parse_or_exit(input);
+
+    let answer: usize = file_system
+        .sizes()
         .into_iter()
+        .filter(|size| size <= &config.small_dir_threshold)
+        .sum();
+    println!("day7, part1: {:?}", answer);
+    answer
+}
+
+pub fn part2() {
+    part2_with(&DiskConfig::default())
+}
+
+pub fn part2_with(config: &DiskConfig) {
+    part2_run(&input(), config);
+}
+fn part2_run(input: &str, config: &DiskConfig) -> Option<(String, usize)> {
+    let file_system = parse_or_exit(input);
+
+    let space = file_system.space_to_free(config);
+    if space == 0 {
+        println!("day7, part2: nothing to delete");
+        return None;
+    }
+    let answer = file_system.smallest_dir_to_free(space);
+    println!("day7, part2: {:?}", answer);
+    answer
+}
+
+pub fn plan(config: &DiskConfig, path: Option<&str>) {
+    let file_system = read_file_system(path);
+
+    match file_system.deletion_plan(file_system.space_to_free(config)) {
+        Some((dirs, freed)) => {
+            dirs.iter().for_each(|dir| println!("{}", dir));
+            let free_space = config.total_space.saturating_sub(file_system.sizes()[ROOT]) + freed;
+            println!(
+                "day7, plan: {} bytes deleted, {} bytes free",
+                freed, free_space
+            );
+        }
+        None => println!("day7, plan: not enough space even deleting /"),
+    }
+}
+
+/// Sizes the puzzle hard-codes, `from_args` reads them from `--threshold`,
+/// `--total` and `--required` flags.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DiskConfig {
+    pub small_dir_threshold: usize,
+    pub total_space: usize,
+    pub required_space: usize,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        DiskConfig {
+            small_dir_threshold: 100000,
+            total_space: 70000000,
+            required_space: 30000000,
+        }
+    }
+}
+
+impl DiskConfig {
+    pub fn from_args(args: &[&str]) -> Self {
+        args.chunks(2)
+            .fold(DiskConfig::default(), |config, flag| match flag {
+                ["--threshold", value] => DiskConfig {
+                    small_dir_threshold: value.parse().unwrap(),
+                    ..config
+                },
+                ["--total", value] => DiskConfig {
+                    total_space: value.parse().unwrap(),
+                    ..config
+                },
+                ["--required", value] => DiskConfig {
+                    required_space: value.parse().unwrap(),
+                    ..config
+                },
+                _ => panic!("unknown flag {:?}", flag),
+            })
+    }
+}
+
+pub fn tree(path: Option<&str>) {
+    print!("{}", read_file_system(path).tree(ROOT));
+}
+
+pub fn du(path: Option<&str>) {
+    print!("{}", read_file_system(path).du(ROOT));
+}
+
+pub fn json(path: Option<&str>) {
+    println!("{}", read_file_system(path).json(ROOT));
+}
+
+pub fn csv(path: Option<&str>) {
+    print!("{}", read_file_system(path).csv(ROOT));
+}
+
+/// Recreates the filesystem of the log at `path` under `target`, the files
+/// being sparse. `find <target> -type f -printf '%s %P\n'` lists the sizes of
+/// the log, `du` does not match as it adds the size of each directory itself.
+pub fn materialize(target: &str, path: Option<&str>) {
+    if let Err(error) = read_file_system(path).materialize(Path::new(target)) {
+        println!("day7, materialize: {}", error);
+    }
+}
+
+pub fn shell(path: Option<&str>) {
+    let file_system = read_file_system(path);
+
+    let mut current_dir = ROOT;
+    let mut lines = io::stdin().lock().lines();
+    for number in 1.. {
+        print!("{} $ ", file_system.path(current_dir));
+        io::stdout().flush().unwrap();
+
+        let command = match lines.next() {
+            Some(command) => command.unwrap(),
+            None => break,
+        };
+        if command.trim() == "exit" {
+            break;
+        }
+
+        match file_system.shell_command(current_dir, &command, number) {
+            Ok((next_dir, output)) => {
+                current_dir = next_dir;
+                print!("{}", output);
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+}
+
+pub fn generate(path: &str, seed: Option<u64>) {
+    println!("{}", generate_log(Path::new(path), seed).unwrap());
+}
+
+pub fn check(path: Option<&str>) {
+    let anomalies = check_log(&fs::read_to_string(path.unwrap_or("day7")).unwrap());
+
+    anomalies.iter().for_each(|anomaly| println!("{}", anomaly));
+    println!("day7, check: {} anomalies", anomalies.len());
+}
+
+/// The one way from a terminal log to the filesystem model.
+fn parse(input: &str) -> Result<FileSystem, ReplayError> {
+    let lines = input
+        .lines()
         .map(str::trim)
-        .map(Line::from)
-        .fold(
-            ("root".to_string(), resulting_line),
-            |(current_dir, mut resulting_lines), line| {
-                let next_dir = associate_dir(&current_dir, &line);
-
-                resulting_lines.push((next_dir.clone(), line));
-                (next_dir, resulting_lines)
+        .zip(1..)
+        .map(|(value, number)| {
+            Line::parse(value).ok_or_else(|| ReplayError::Unparsable(number, value.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    FileSystem::from_lines(lines)
+}
+
+fn read_file_system(path: Option<&str>) -> FileSystem {
+    parse_or_exit(&fs::read_to_string(path.unwrap_or("day7")).unwrap())
+}
+
+/// `parse` for the commands, a broken log is printed and ends the process.
+fn parse_or_exit(input: &str) -> FileSystem {
+    parse(input).unwrap_or_else(|error| {
+        eprintln!("day7: {}", error);
+        process::exit(1)
+    })
+}
+
+/// `cd`, `ls` and `rm` take paths, absolute or relative to the current
+/// directory. `WorkingDir` is the path `pwd` prints.
+#[derive(Debug, PartialEq, Eq)]
+enum Line {
+    Cd(String),
+    Ls(Option<String>),
+    Pwd,
+    Rm(String),
+    WorkingDir(String),
+    Dir(String),
+    File(usize, String),
+}
+
+#[cfg(test)]
+impl From<&str> for Line {
+    fn from(value: &str) -> Self {
+        Line::parse(value).unwrap_or_else(|| panic!("parsing line \"{}\" failed", value))
+    }
+}
+
+impl Line {
+    fn parse(value: &str) -> Option<Self> {
+        if let Some(captures) = CD_REGEX.captures(value) {
+            Some(Self::Cd(captures[1].to_string()))
+        } else if let Some(captures) = LS_REGEX.captures(value) {
+            Some(Self::Ls(
+                captures.get(1).map(|path| path.as_str().to_string()),
+            ))
+        } else if PWD_REGEX.is_match(value) {
+            Some(Self::Pwd)
+        } else if let Some(captures) = RM_REGEX.captures(value) {
+            Some(Self::Rm(captures[1].to_string()))
+        } else if value.starts_with('/') {
+            Some(Self::WorkingDir(value.to_string()))
+        } else if let Some(captures) = DIR_REGEX.captures(value) {
+            Some(Self::Dir(captures[1].to_string()))
+        } else {
+            let captures = FILE_REGEX.captures(value)?;
+            Some(Self::File(
+                captures[1].parse().ok()?,
+                captures[2].to_string(),
+            ))
+        }
+    }
+}
+
+const ROOT: usize = 0;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Dir {
+    name: String,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    files: Vec<(String, usize)>,
+}
+
+/// Directories live in an arena indexed by `usize`, the root being `ROOT`. A
+/// directory is always created after its parent so it gets a greater index.
+#[derive(Debug, PartialEq, Eq)]
+struct FileSystem {
+    dirs: Vec<Dir>,
+}
+
+impl FileSystem {
+    fn new() -> Self {
+        FileSystem {
+            dirs: vec![Dir {
+                name: "/".to_string(),
+                parent: None,
+                children: vec![],
+                files: vec![],
+            }],
+        }
+    }
+
+    /// Replays the terminal session, lines being numbered from 1 in errors.
+    /// The replay follows the current directory and the one being listed,
+    /// which differ after `ls <path>`.
+    fn from_lines<I>(lines: I) -> Result<Self, ReplayError>
+    where
+        I: IntoIterator<Item = Line>,
+    {
+        let mut file_system = FileSystem::new();
+
+        lines.into_iter().zip(1..).try_fold(
+            (ROOT, ROOT),
+            |(current_dir, listed_dir), (line, number)| match line {
+                Line::Cd(path) => file_system
+                    .change_dir(current_dir, &path, number)
+                    .map(|dir| (dir, dir)),
+                Line::Ls(None) | Line::Pwd => Ok((current_dir, current_dir)),
+                Line::Ls(Some(path)) => file_system
+                    .change_dir(current_dir, &path, number)
+                    .map(|dir| (current_dir, dir))
+                    .map_err(ReplayError::in_ls),
+                Line::Rm(path) => file_system
+                    .remove(current_dir, &path, number)
+                    .map(|dir| (dir, dir)),
+                Line::WorkingDir(path) if path == file_system.path(current_dir) => {
+                    Ok((current_dir, listed_dir))
+                }
+                Line::WorkingDir(path) => Err(ReplayError::WrongDir(
+                    number,
+                    file_system.path(current_dir),
+                    path,
+                )),
+                Line::Dir(dir) => {
+                    check_name(&dir, number)?;
+                    file_system.add_dir(listed_dir, &dir);
+                    Ok((current_dir, listed_dir))
+                }
+                Line::File(size, name) => {
+                    check_name(&name, number)?;
+                    file_system.add_file(listed_dir, name, size, number)?;
+                    Ok((current_dir, listed_dir))
+                }
             },
+        )?;
+
+        Ok(file_system)
+    }
+
+    /// Directory at `path`, absolute or relative to `current_dir`, one
+    /// component at a time.
+    fn change_dir(
+        &self,
+        current_dir: usize,
+        path: &str,
+        line: usize,
+    ) -> Result<usize, ReplayError> {
+        let (start, path) = match path.strip_prefix('/') {
+            Some(path) => (ROOT, path),
+            None => (current_dir, path),
+        };
+
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(start, |dir, component| match component {
+                "." => Ok(dir),
+                ".." => self.dirs[dir].parent.ok_or(ReplayError::AboveRoot(line)),
+                _ => self
+                    .child(dir, component)
+                    .ok_or_else(|| ReplayError::UnknownDir(line, component.to_string())),
+            })
+    }
+
+    /// Lenient `change_dir` for `check_log`: unknown directories are reported
+    /// then created, `..` above `/` is reported and stays on `/`.
+    fn change_dir_or_create(
+        &mut self,
+        current_dir: usize,
+        path: &str,
+        line: usize,
+        anomalies: &mut Vec<ReplayError>,
+    ) -> usize {
+        let (start, path) = match path.strip_prefix('/') {
+            Some(path) => (ROOT, path),
+            None => (current_dir, path),
+        };
+
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .fold(start, |dir, component| match component {
+                "." => dir,
+                ".." => self.dirs[dir].parent.unwrap_or_else(|| {
+                    anomalies.push(ReplayError::AboveRoot(line));
+                    dir
+                }),
+                _ => self.child(dir, component).unwrap_or_else(|| {
+                    anomalies.push(ReplayError::UnknownDir(line, component.to_string()));
+                    self.add_dir(dir, component)
+                }),
+            })
+    }
+
+    /// Directory holding the last component of `path`, and that component.
+    fn split_path<'a>(
+        &self,
+        current_dir: usize,
+        path: &'a str,
+        line: usize,
+    ) -> Result<(usize, &'a str), ReplayError> {
+        match path.trim_end_matches('/').rsplit_once('/') {
+            Some(("", name)) => Ok((ROOT, name)),
+            Some((parent, name)) => Ok((self.change_dir(current_dir, parent, line)?, name)),
+            None => Ok((current_dir, path.trim_end_matches('/'))),
+        }
+    }
+
+    /// Removes the file or the directory, with everything below it, at
+    /// `path`. Directories are dropped from the arena, whose indices shift, so
+    /// it returns where `current_dir` went.
+    fn remove(
+        &mut self,
+        current_dir: usize,
+        path: &str,
+        line: usize,
+    ) -> Result<usize, ReplayError> {
+        let (parent, name) = self.split_path(current_dir, path, line)?;
+
+        let files = &mut self.dirs[parent].files;
+        if let Some(index) = files.iter().position(|(file, _)| file == name) {
+            files.remove(index);
+            return Ok(current_dir);
+        }
+
+        let removed_dir = self
+            .child(parent, name)
+            .ok_or_else(|| ReplayError::UnknownPath(line, path.to_string()))?;
+        let mut removed = vec![false; self.dirs.len()];
+        self.descendants(removed_dir)
+            .into_iter()
+            .for_each(|dir| removed[dir] = true);
+        if removed[current_dir] {
+            return Err(ReplayError::RemovedWorkingDir(line, self.path(removed_dir)));
+        }
+
+        let new_indices: Vec<usize> = removed
+            .iter()
+            .scan(0, |next, &removed| {
+                *next += usize::from(!removed);
+                Some(*next - 1)
+            })
+            .collect();
+        self.dirs = std::mem::take(&mut self.dirs)
+            .into_iter()
+            .zip(removed)
+            .filter(|(_, removed)| !removed)
+            .map(|(dir, _)| Dir {
+                parent: dir.parent.map(|parent| new_indices[parent]),
+                children: dir
+                    .children
+                    .iter()
+                    .filter(|&&child| child != removed_dir)
+                    .map(|&child| new_indices[child])
+                    .collect(),
+                ..dir
+            })
+            .collect();
+
+        Ok(new_indices[current_dir])
+    }
+
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        self.dirs[dir]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.dirs[child].name == name)
+    }
+
+    fn add_dir(&mut self, parent: usize, name: &str) -> usize {
+        if let Some(child) = self.child(parent, name) {
+            return child;
+        }
+
+        self.dirs.push(Dir {
+            name: name.to_string(),
+            parent: Some(parent),
+            children: vec![],
+            files: vec![],
+        });
+        let child = self.dirs.len() - 1;
+        self.dirs[parent].children.push(child);
+        child
+    }
+
+    /// Bytes still missing to reach the required free space.
+    fn space_to_free(&self, config: &DiskConfig) -> usize {
+        let unused_space = config.total_space.saturating_sub(self.sizes()[ROOT]);
+
+        config.required_space.saturating_sub(unused_space)
+    }
+
+    /// Path and size of the smallest directory freeing at least `space` once
+    /// deleted, `None` when nothing needs deleting or even `/` is not enough.
+    fn smallest_dir_to_free(&self, space: usize) -> Option<(String, usize)> {
+        if space == 0 {
+            return None;
+        }
+
+        self.sizes()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, size)| size >= space)
+            .min_by_key(|&(_, size)| size)
+            .map(|(dir, size)| (self.path(dir), size))
+    }
+
+    /// Non nested directories freeing at least `space` while deleting as few
+    /// bytes as possible, with the bytes deleted.
+    ///
+    /// Knapsack over the directories in pre-order, see `scan_deletions`. The
+    /// backtrack scans again for each deleted directory, one directory at a time,
+    /// instead of keeping every row around.
+    fn deletion_plan(&self, space: usize) -> Option<(Vec<String>, usize)> {
+        if space == 0 {
+            return Some((vec![], 0));
+        }
+        let (_, bound) = self.smallest_dir_to_free(space)?;
+
+        let sizes = self.sizes();
+        let order = self.descendants(ROOT);
+        let mut subtree_lenghts = vec![1; self.dirs.len()];
+        for dir in (0..self.dirs.len()).rev() {
+            if let Some(parent) = self.dirs[dir].parent {
+                subtree_lenghts[parent] += subtree_lenghts[dir];
+            }
+        }
+        let is_reachable = |row: &[u64], sum: usize| row[sum / 64] & (1 << (sum % 64)) != 0;
+        let scan = |visit: &mut dyn FnMut(usize, &[u64]) -> bool| {
+            scan_deletions(&order, &subtree_lenghts, &sizes, bound, visit)
+        };
+
+        let reachable = scan(&mut |_, _| false);
+        let freed = (space..=bound).find(|&sum| is_reachable(&reachable, sum))?;
+
+        let mut chosen = vec![];
+        let (mut end, mut sum) = (order.len(), freed);
+        while sum > 0 {
+            let mut deleted = None;
+            scan(&mut |position, row| {
+                let dir = order[position];
+                let fits = position + subtree_lenghts[dir] <= end
+                    && sizes[dir] <= sum
+                    && is_reachable(row, sum - sizes[dir]);
+                if fits {
+                    deleted = Some(position);
+                }
+                fits || position >= end
+            });
+
+            let deleted = deleted.unwrap();
+            chosen.push(self.path(order[deleted]));
+            sum -= sizes[order[deleted]];
+            end = deleted;
+        }
+
+        chosen.sort();
+        Some((chosen, freed))
+    }
+
+    /// Files are known by name within their directory, so listing a directory
+    /// again does not count its files twice.
+    fn add_file(
+        &mut self,
+        dir: usize,
+        name: String,
+        size: usize,
+        line: usize,
+    ) -> Result<(), ReplayError> {
+        match self.dirs[dir].files.iter().find(|(file, _)| *file == name) {
+            Some(&(_, known_size)) if known_size != size => Err(ReplayError::SizeConflict(
+                line,
+                self.file_path(dir, &name),
+                known_size,
+                size,
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.dirs[dir].files.push((name, size));
+                Ok(())
+            }
+        }
+    }
+
+    /// Recursive size of every directory, by index. Walking the arena backwards
+    /// sees every child before its parent.
+    fn sizes(&self) -> Vec<usize> {
+        let mut sizes: Vec<usize> = self
+            .dirs
+            .iter()
+            .map(|dir| dir.files.iter().map(|(_, size)| size).sum())
+            .collect();
+
+        for dir in (0..self.dirs.len()).rev() {
+            if let Some(parent) = self.dirs[dir].parent {
+                sizes[parent] += sizes[dir];
+            }
+        }
+
+        sizes
+    }
+
+    fn path(&self, dir: usize) -> String {
+        match self.dirs[dir].parent {
+            None => "/".to_string(),
+            Some(ROOT) => format!("/{}", self.dirs[dir].name),
+            Some(parent) => format!("{}/{}", self.path(parent), self.dirs[dir].name),
+        }
+    }
+
+    fn file_path(&self, dir: usize, name: &str) -> String {
+        match dir {
+            ROOT => format!("/{}", name),
+            dir => format!("{}/{}", self.path(dir), name),
+        }
+    }
+
+    /// Runs the `number`th shell command from `current_dir`: `cd`, `ls` and
+    /// `pwd` go through the terminal log parser, `du`, `tree` and `find <name>` are shell
+    /// only. Returns the directory to continue from and the text to print.
+    fn shell_command(
+        &self,
+        current_dir: usize,
+        command: &str,
+        number: usize,
+    ) -> Result<(usize, String), String> {
+        let command = command.trim();
+
+        if command == "du" {
+            return Ok((current_dir, self.du(current_dir)));
+        }
+        if command == "tree" {
+            return Ok((current_dir, self.tree(current_dir)));
+        }
+        if let Some(name) = command.strip_prefix("find ") {
+            return Ok((current_dir, self.find(current_dir, name.trim())));
+        }
+
+        match Line::parse(&format!("$ {}", command)) {
+            Some(Line::Cd(path)) => self
+                .change_dir(current_dir, &path, number)
+                .map(|dir| (dir, "".to_string()))
+                .map_err(|error| error.to_string()),
+            Some(Line::Ls(None)) => Ok((current_dir, self.ls(current_dir))),
+            Some(Line::Ls(Some(path))) => self
+                .change_dir(current_dir, &path, number)
+                .map(|dir| (current_dir, self.ls(dir)))
+                .map_err(|error| error.in_ls().to_string()),
+            Some(Line::Pwd) => Ok((current_dir, format!("{}\n", self.path(current_dir)))),
+            _ => Err(format!("unknown command \"{}\"", command)),
+        }
+    }
+
+    /// Lists a directory the way the terminal log does.
+    fn ls(&self, dir: usize) -> String {
+        let dirs = self.dirs[dir]
+            .children
+            .iter()
+            .map(|&child| format!("dir {}\n", self.dirs[child].name));
+        let files = self.dirs[dir]
+            .files
+            .iter()
+            .map(|(name, size)| format!("{} {}\n", size, name));
+
+        dirs.chain(files).collect()
+    }
+
+    /// Renders `dir` like the puzzle listing, entries sorted by name, with the
+    /// recursive total of each directory.
+    fn tree(&self, dir: usize) -> String {
+        let mut lines = vec![];
+        self.tree_lines(dir, 0, &self.sizes(), &mut lines);
+        lines.concat()
+    }
+
+    fn tree_lines(&self, dir: usize, depth: usize, sizes: &[usize], lines: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        lines.push(format!(
+            "{}- {} (dir, size={})\n",
+            indent, self.dirs[dir].name, sizes[dir]
+        ));
+
+        let dirs = self.dirs[dir]
+            .children
+            .iter()
+            .map(|&child| (self.dirs[child].name.as_str(), Some(child), sizes[child]));
+        let files = self.dirs[dir]
+            .files
+            .iter()
+            .map(|(name, size)| (name.as_str(), None, *size));
+
+        dirs.chain(files)
+            .sorted_by_key(|(name, _, _)| *name)
+            .for_each(|(name, child, size)| match child {
+                Some(child) => self.tree_lines(child, depth + 1, sizes, lines),
+                None => lines.push(format!("{}  - {} (file, size={})\n", indent, name, size)),
+            });
+    }
+
+    /// `du` like table of `dir` and the directories below it, biggest first.
+    fn du(&self, dir: usize) -> String {
+        let sizes = self.sizes();
+
+        self.descendants(dir)
+            .into_iter()
+            .map(|dir| (sizes[dir], self.path(dir)))
+            .sorted_by(|(size_a, path_a), (size_b, path_b)| {
+                size_b.cmp(size_a).then_with(|| path_a.cmp(path_b))
+            })
+            .map(|(size, path)| format!("{}\t{}\n", size, path))
+            .collect()
+    }
+
+    /// Nested JSON document of `dir`: every entry has a `name`, a `type` and a
+    /// `size`, recursive for directories, which also list their `children`.
+    fn json(&self, dir: usize) -> String {
+        self.json_dir(dir, &self.sizes())
+    }
+
+    fn json_dir(&self, dir: usize, sizes: &[usize]) -> String {
+        let dirs = self.dirs[dir]
+            .children
+            .iter()
+            .map(|&child| self.json_dir(child, sizes));
+        let files = self.dirs[dir].files.iter().map(|(name, size)| {
+            format!(
+                "{{\"name\":{},\"type\":\"file\",\"size\":{}}}",
+                json_string(name),
+                size
+            )
+        });
+
+        format!(
+            "{{\"name\":{},\"type\":\"dir\",\"size\":{},\"children\":[{}]}}",
+            json_string(&self.dirs[dir].name),
+            sizes[dir],
+            dirs.chain(files).join(",")
+        )
+    }
+
+    /// Flat CSV of `dir` and the directories below it, parents first.
+    fn csv(&self, dir: usize) -> String {
+        let sizes = self.sizes();
+
+        let rows: String = self
+            .descendants(dir)
+            .into_iter()
+            .map(|dir| format!("{},{}\n", csv_field(&self.path(dir)), sizes[dir]))
+            .collect();
+
+        format!("path,size\n{}", rows)
+    }
+
+    /// Creates every directory under `target`, and every file with its size
+    /// but no data written. `target` must be missing or empty, and nothing is
+    /// created when a file and a directory share a path.
+    fn materialize(&self, target: &Path) -> io::Result<()> {
+        let collision = self.descendants(ROOT).into_iter().find_map(|dir| {
+            self.dirs[dir]
+                .files
+                .iter()
+                .find(|(name, _)| self.child(dir, name).is_some())
+                .map(|(name, _)| self.file_path(dir, name))
+        });
+        if let Some(path) = collision {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is both a file and a directory", path),
+            ));
+        }
+
+        fs::create_dir_all(target)?;
+        if fs::read_dir(target)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", target.display()),
+            ));
+        }
+
+        for dir in self.descendants(ROOT) {
+            let path = target.join(self.path(dir).trim_start_matches('/'));
+            if dir != ROOT {
+                fs::create_dir(&path)?;
+            }
+
+            for (name, size) in &self.dirs[dir].files {
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(path.join(name))?
+                    .set_len(*size as u64)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `dir` and every directory below it, parents first.
+    fn descendants(&self, dir: usize) -> Vec<usize> {
+        let mut descendants = vec![];
+        let mut to_visit = vec![dir];
+
+        while let Some(dir) = to_visit.pop() {
+            descendants.push(dir);
+            to_visit.extend(self.dirs[dir].children.iter().rev());
+        }
+
+        descendants
+    }
+
+    /// Paths of the directories and files below `dir` whose name contains `name`.
+    fn find(&self, dir: usize, name: &str) -> String {
+        self.descendants(dir)
+            .into_iter()
+            .flat_map(|dir| {
+                let found_dir =
+                    (dir != ROOT && self.dirs[dir].name.contains(name)).then(|| self.path(dir));
+                let found_files = self.dirs[dir]
+                    .files
+                    .iter()
+                    .filter(|(file, _)| file.contains(name))
+                    .map(move |(file, _)| self.file_path(dir, file));
+
+                found_dir.into_iter().chain(found_files)
+            })
+            .map(|path| format!("{}\n", path))
+            .collect()
+    }
+}
+
+/// Directory being listed, line of its `ls`, the entries seen so far and
+/// whether the listing was already reported as changed.
+type Listing = (usize, usize, Vec<String>, bool);
+
+/// Replays the log like `parse` but goes on after an anomaly, reporting them
+/// all in line order. The first listing of a directory is the reference the
+/// next ones are compared to, until an `rm` changes it: the parent of the
+/// removed path and the directories removed forget their first listing.
+/// A changed listing is reported once, at its first new entry or at its `ls`
+/// when entries are only missing, instead of the size conflicts of its
+/// entries. A path is only the working directory right after a `pwd`.
+fn check_log(input: &str) -> Vec<ReplayError> {
+    let mut file_system = FileSystem::new();
+    let mut anomalies = vec![];
+    let mut current_dir = ROOT;
+    let mut listing: Option<Listing> = None;
+    let mut listings: HashMap<String, (usize, Vec<String>)> = HashMap::new();
+    let mut pwd = false;
+
+    for (value, number) in input.lines().map(str::trim).zip(1..) {
+        let line = Line::parse(value);
+        let after_pwd = std::mem::replace(&mut pwd, line == Some(Line::Pwd));
+        let line = match line {
+            Some(line) => line,
+            None => {
+                anomalies.push(ReplayError::Unparsable(number, value.to_string()));
+                continue;
+            }
+        };
+        if !matches!(line, Line::Dir(_) | Line::File(_, _) | Line::WorkingDir(_)) {
+            if let Some(listing) = listing.take() {
+                end_listing(&file_system, listing, &mut listings, &mut anomalies);
+            }
+        }
+
+        match line {
+            Line::Cd(path) => {
+                current_dir =
+                    file_system.change_dir_or_create(current_dir, &path, number, &mut anomalies)
+            }
+            Line::Ls(None) => listing = Some((current_dir, number, vec![], false)),
+            Line::Ls(Some(path)) => {
+                let mut ls_anomalies = vec![];
+                let dir =
+                    file_system.change_dir_or_create(current_dir, &path, number, &mut ls_anomalies);
+                anomalies.extend(ls_anomalies.into_iter().map(ReplayError::in_ls));
+                listing = Some((dir, number, vec![], false));
+            }
+            Line::Pwd => {}
+            Line::WorkingDir(path) if !after_pwd => {
+                anomalies.push(ReplayError::UnlistedOutput(number, path))
+            }
+            Line::WorkingDir(path) if path == file_system.path(current_dir) => {}
+            Line::WorkingDir(path) => anomalies.push(ReplayError::WrongDir(
+                number,
+                file_system.path(current_dir),
+                path,
+            )),
+            Line::Rm(path) => {
+                let removed =
+                    file_system
+                        .split_path(current_dir, &path, number)
+                        .map(|(parent, name)| {
+                            (
+                                file_system.path(parent),
+                                file_system.file_path(parent, name),
+                            )
+                        });
+                match file_system.remove(current_dir, &path, number) {
+                    Ok(dir) => {
+                        current_dir = dir;
+                        if let Ok((parent, removed)) = removed {
+                            let below = format!("{}/", removed);
+                            listings.retain(|listed, _| {
+                                *listed != parent
+                                    && *listed != removed
+                                    && !listed.starts_with(&below)
+                            });
+                        }
+                    }
+                    Err(error) => anomalies.push(error),
+                }
+            }
+            Line::Dir(_) | Line::File(_, _) => {
+                let (dir, relisted) = match &mut listing {
+                    Some((dir, _, entries, reported)) => {
+                        entries.push(value.to_string());
+                        let path = file_system.path(*dir);
+                        let first = listings.get(&path);
+                        if let Some((first_line, first_entries)) = first {
+                            let new = first_entries
+                                .binary_search_by(|entry| entry.as_str().cmp(value))
+                                .is_err();
+                            if new && !*reported {
+                                anomalies.push(ReplayError::ListingChanged(
+                                    number,
+                                    path,
+                                    *first_line,
+                                ));
+                                *reported = true;
+                            }
+                        }
+                        (*dir, first.is_some())
+                    }
+                    None => {
+                        anomalies.push(ReplayError::UnlistedOutput(number, value.to_string()));
+                        (current_dir, false)
+                    }
+                };
+                let added = match line {
+                    Line::Dir(name) => {
+                        check_name(&name, number).map(|_| file_system.add_dir(dir, &name))
+                    }
+                    Line::File(size, name) => check_name(&name, number)
+                        .and_then(|_| file_system.add_file(dir, name, size, number))
+                        .map(|_| dir),
+                    _ => unreachable!(),
+                };
+                match added {
+                    Err(ReplayError::SizeConflict(..)) if relisted => {}
+                    Err(error) => anomalies.push(error),
+                    Ok(_) => {}
+                }
+            }
+        }
+    }
+    if let Some(listing) = listing {
+        end_listing(&file_system, listing, &mut listings, &mut anomalies);
+    }
+
+    anomalies.sort_by_key(ReplayError::line);
+    anomalies
+}
+
+fn end_listing(
+    file_system: &FileSystem,
+    (dir, line, mut entries, reported): Listing,
+    listings: &mut HashMap<String, (usize, Vec<String>)>,
+    anomalies: &mut Vec<ReplayError>,
+) {
+    entries.sort();
+    let path = file_system.path(dir);
+
+    match listings.get(&path) {
+        Some((first_line, first_entries)) if *first_entries != entries && !reported => {
+            anomalies.push(ReplayError::ListingChanged(line, path, *first_line))
+        }
+        Some(_) => {}
+        None => {
+            listings.insert(path, (line, entries));
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ReplayError {
+    AboveRoot(usize),
+    UnknownDir(usize, String),
+    UnknownLsDir(usize, String),
+    InvalidName(usize, String),
+    SizeConflict(usize, String, usize, usize),
+    WrongDir(usize, String, String),
+    UnknownPath(usize, String),
+    RemovedWorkingDir(usize, String),
+    Unparsable(usize, String),
+    UnlistedOutput(usize, String),
+    ListingChanged(usize, String, usize),
+}
+
+impl ReplayError {
+    /// `UnknownDir` met on the path of an `ls` rather than a `cd`.
+    fn in_ls(self) -> Self {
+        match self {
+            ReplayError::UnknownDir(line, name) => ReplayError::UnknownLsDir(line, name),
+            error => error,
+        }
+    }
+
+    fn line(&self) -> usize {
+        match self {
+            ReplayError::AboveRoot(line)
+            | ReplayError::UnknownDir(line, _)
+            | ReplayError::UnknownLsDir(line, _)
+            | ReplayError::InvalidName(line, _)
+            | ReplayError::SizeConflict(line, _, _, _)
+            | ReplayError::WrongDir(line, _, _)
+            | ReplayError::UnknownPath(line, _)
+            | ReplayError::RemovedWorkingDir(line, _)
+            | ReplayError::Unparsable(line, _)
+            | ReplayError::UnlistedOutput(line, _)
+            | ReplayError::ListingChanged(line, _, _) => *line,
+        }
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::AboveRoot(line) => write!(f, "line {}: cd .. above /", line),
+            ReplayError::UnknownDir(line, name) => {
+                write!(f, "line {}: cd into unlisted directory \"{}\"", line, name)
+            }
+            ReplayError::UnknownLsDir(line, name) => {
+                write!(f, "line {}: ls of unlisted directory \"{}\"", line, name)
+            }
+            ReplayError::InvalidName(line, name) => {
+                write!(f, "line {}: invalid name \"{}\"", line, name)
+            }
+            ReplayError::SizeConflict(line, path, known_size, size) => write!(
+                f,
+                "line {}: {} listed with size {} after {}",
+                line, path, size, known_size
+            ),
+            ReplayError::WrongDir(line, path, printed) => {
+                write!(f, "line {}: pwd printed {} from {}", line, printed, path)
+            }
+            ReplayError::UnknownPath(line, path) => {
+                write!(f, "line {}: rm of unlisted \"{}\"", line, path)
+            }
+            ReplayError::RemovedWorkingDir(line, path) => {
+                write!(f, "line {}: rm of {} from inside it", line, path)
+            }
+            ReplayError::Unparsable(line, value) => {
+                write!(f, "line {}: unknown line \"{}\"", line, value)
+            }
+            ReplayError::UnlistedOutput(line, value) => {
+                write!(f, "line {}: \"{}\" outside of an ls listing", line, value)
+            }
+            ReplayError::ListingChanged(line, path, first_line) => write!(
+                f,
+                "line {}: {} listed differently than on line {}",
+                line, path, first_line
+            ),
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let escaped: String = value
+        .chars()
+        .map(|char| match char {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            char if (char as u32) < 0x20 => format!("\\u{:04x}", char as u32),
+            char => char.to_string(),
+        })
+        .collect();
+
+    format!("\"{}\"", escaped)
+}
+
+/// Quoted only when needed, as RFC 4180 asks.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Terminal session exploring `root` depth first: listings and visits are in
+/// name order, or shuffled from `seed`. File sizes are apparent sizes, as
+/// `du --apparent-size --bytes` reports, symbolic links are left out.
+fn generate_log(root: &Path, seed: Option<u64>) -> io::Result<String> {
+    let mut shuffler = seed.map(XorShift::new);
+    let mut lines = vec!["$ cd /".to_string()];
+
+    log_dir(root, &mut shuffler, &mut lines)?;
+
+    Ok(lines.join("\n"))
+}
+
+fn log_dir(dir: &Path, shuffler: &mut Option<XorShift>, lines: &mut Vec<String>) -> io::Result<()> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        if metadata.is_dir() || metadata.is_file() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            entries.push((name, metadata.is_dir(), metadata.len()));
+        }
+    }
+    entries.sort();
+    if let Some(shuffler) = shuffler {
+        shuffler.shuffle(&mut entries);
+    }
+
+    lines.push("$ ls".to_string());
+    entries
+        .iter()
+        .for_each(|(name, is_dir, size)| match is_dir {
+            true => lines.push(format!("dir {}", name)),
+            false => lines.push(format!("{} {}", size, name)),
+        });
+
+    let mut subdirs: Vec<&String> = entries
+        .iter()
+        .filter(|(_, is_dir, _)| *is_dir)
+        .map(|(name, _, _)| name)
+        .collect();
+    if let Some(shuffler) = shuffler {
+        shuffler.shuffle(&mut subdirs);
+    }
+    for name in subdirs {
+        lines.push(format!("$ cd {}", name));
+        log_dir(&dir.join(name), shuffler, lines)?;
+        lines.push("$ cd ..".to_string());
+    }
+
+    Ok(())
+}
+
+/// Small deterministic generator, enough to shuffle generated sessions.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        XorShift(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = (self.next() % (index as u64 + 1)) as usize;
+            items.swap(index, other);
+        }
+    }
+}
+
+/// Sums of non nested directories, up to `bound`, that can be deleted before
+/// each position of the pre-order `order`: at each position a directory is
+/// either skipped or deleted, deleting it jumps past its subtree. `visit` sees
+/// each position with its row until it returns `true`, the last row is
+/// returned.
+///
+/// Skipping carries every sum over, so rows only grow along the pre-order:
+/// only the current row is kept, with the rows pending at the end of the
+/// subtrees being visited, one per ancestor at most. Leaves are deleted in
+/// place and rows only go up to the greatest sum they can hold.
+fn scan_deletions(
+    order: &[usize],
+    subtree_lenghts: &[usize],
+    sizes: &[usize],
+    bound: usize,
+    visit: &mut dyn FnMut(usize, &[u64]) -> bool,
+) -> Vec<u64> {
+    let words = bound / 64 + 1;
+    let (mut current, mut reach) = (vec![0u64; words], 0);
+    current[0] = 1;
+    let mut pending: HashMap<usize, (Vec<u64>, usize)> = HashMap::new();
+    let mut spare_rows = vec![];
+
+    for (position, &dir) in order.iter().enumerate() {
+        if let Some((mut row, row_reach)) = pending.remove(&position) {
+            or_shifted(&mut current, &row[..=row_reach / 64], 0);
+            reach = reach.max(row_reach);
+            row[..=row_reach / 64].fill(0);
+            spare_rows.push(row);
+        }
+        if visit(position, &current) {
+            return current;
+        }
+        if sizes[dir] > bound {
+            continue;
+        }
+
+        let shifted_reach = (reach + sizes[dir]).min(bound);
+        match position + subtree_lenghts[dir] {
+            next if next == position + 1 => {
+                or_shifted_in_place(&mut current[..=shifted_reach / 64], sizes[dir]);
+                reach = shifted_reach;
+            }
+            next => {
+                let (row, row_reach) = pending
+                    .entry(next)
+                    .or_insert_with(|| (spare_rows.pop().unwrap_or_else(|| vec![0; words]), 0));
+                or_shifted(row, &current[..=reach / 64], sizes[dir]);
+                *row_reach = shifted_reach.max(*row_reach);
+            }
+        }
+    }
+    if let Some((row, _)) = pending.remove(&order.len()) {
+        or_shifted(&mut current, &row, 0);
+    }
+
+    current
+}
+
+/// `target |= source << shift` on bitsets stored as `u64` words, bits shifted
+/// past the end of `target` are dropped.
+fn or_shifted(target: &mut [u64], source: &[u64], shift: usize) {
+    let (words, bits) = (shift / 64, shift % 64);
+
+    for (index, &word) in source.iter().enumerate().filter(|(_, &word)| word != 0) {
+        if let Some(shifted) = target.get_mut(index + words) {
+            *shifted |= word << bits;
+        }
+        if bits > 0 {
+            if let Some(shifted) = target.get_mut(index + words + 1) {
+                *shifted |= word >> (64 - bits);
+            }
+        }
+    }
+}
+
+/// `row |= row << shift`, highest words first so that no bit moves twice.
+fn or_shifted_in_place(row: &mut [u64], shift: usize) {
+    let (words, bits) = (shift / 64, shift % 64);
+
+    for index in (words..row.len()).rev() {
+        let low = match (bits, index - words) {
+            (0, _) | (_, 0) => 0,
+            (bits, source) => row[source - 1] >> (64 - bits),
+        };
+        row[index] |= row[index - words] << bits | low;
+    }
+}
+
+/// Names are single path components.
+fn check_name(name: &str, line: usize) -> Result<(), ReplayError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        Err(ReplayError::InvalidName(line, name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use std::{env, fs, io, process};
+
+    use crate::day7::{
+        check_log, csv_field, generate_log, json_string, or_shifted, or_shifted_in_place, parse,
+        part1_run, part2_run, DiskConfig, FileSystem, Line, ReplayError, ROOT,
+    };
+
+    #[test]
+    fn parse_line_bim() {
+        assert_eq!(Line::from("$ cd boum"), Line::Cd("boum".to_string()));
+        assert_eq!(Line::from("$ ls"), Line::Ls(None));
+        assert_eq!(Line::from("$ ls /a/b"), Line::Ls(Some("/a/b".to_string())));
+        assert_eq!(Line::from("$ pwd"), Line::Pwd);
+        assert_eq!(Line::from("$ rm -rf a/b"), Line::Rm("a/b".to_string()));
+        assert_eq!(Line::from("$ rm b.txt"), Line::Rm("b.txt".to_string()));
+        assert_eq!(Line::from("/a/b"), Line::WorkingDir("/a/b".to_string()));
+        assert_eq!(Line::from("dir bim"), Line::Dir("bim".to_string()));
+        assert_eq!(
+            Line::from("1996 boum"),
+            Line::File(1996, "boum".to_string())
+        );
+        assert_eq!(
+            Line::from("12 notes dir x.txt"),
+            Line::File(12, "notes dir x.txt".to_string())
+        );
+        assert_eq!(Line::from("7 $ cd y"), Line::File(7, "$ cd y".to_string()));
+        assert_eq!(Line::from("dir 12 b"), Line::Dir("12 b".to_string()));
+        assert_eq!(Line::parse("99999999999999999999999 big"), None);
+    }
+
+    const EXAMPLE: &str = "$ cd /
+        $ ls
+        dir a
+        14848514 b.txt
+        8504156 c.dat
+        dir d
+        $ cd a
+        $ ls
+        dir e
+        29116 f
+        2557 g
+        62596 h.lst
+        $ cd e
+        $ ls
+        584 i
+        $ cd ..
+        $ cd ..
+        $ cd d
+        $ ls
+        4060174 j
+        8033020 d.log
+        5626152 d.ext
+        7214296 k";
+
+    fn example() -> FileSystem {
+        parse(EXAMPLE).unwrap()
+    }
+
+    #[test]
+    fn from_lines_bim() {
+        let file_system = example();
+
+        assert_eq!(file_system.dirs.len(), 4);
+        assert_eq!(file_system.child(ROOT, "a"), Some(1));
+        assert_eq!(file_system.child(ROOT, "d"), Some(2));
+        assert_eq!(file_system.child(1, "e"), Some(3));
+        assert_eq!(file_system.child(ROOT, "e"), None);
+        assert_eq!(file_system.dirs[3].parent, Some(1));
+        assert_eq!(file_system.dirs[3].files, vec![("i".to_string(), 584)]);
+
+        let file_system = FileSystem::from_lines(vec![
+            Line::Cd("/".to_string()),
+            Line::Ls(None),
+            Line::Dir("a".to_string()),
+            Line::Cd("a".to_string()),
+            Line::Cd("..".to_string()),
+            Line::Ls(None),
+            Line::Dir("a".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(file_system.dirs.len(), 2);
+    }
+
+    #[test]
+    fn from_lines_boum() {
+        let file_system = parse(
+            "$ cd /
+            $ ls
+            dir root
+            1 a
+            $ cd root
+            $ ls
+            2 a",
+        )
+        .unwrap();
+        assert_eq!(file_system.path(1), "/root");
+        assert_eq!(file_system.sizes(), vec![3, 2]);
+
+        assert_eq!(parse("$ cd /\n$ cd .."), Err(ReplayError::AboveRoot(2)));
+        assert_eq!(
+            parse("$ cd /\n$ ls\ndir a\n$ cd a\n$ cd ..\n$ cd .."),
+            Err(ReplayError::AboveRoot(6))
+        );
+        assert_eq!(
+            parse("$ cd /\n$ ls\ndir a\n$ cd b"),
+            Err(ReplayError::UnknownDir(4, "b".to_string()))
+        );
+        assert_eq!(
+            parse("$ cd /\n$ ls\ndir a/b"),
+            Err(ReplayError::InvalidName(3, "a/b".to_string()))
+        );
+        assert_eq!(
+            parse("$ cd /\n$ ls\ndir a\n$ cd a/b"),
+            Err(ReplayError::UnknownDir(4, "b".to_string()))
+        );
+        assert_eq!(
+            parse("$ ls\n1 .."),
+            Err(ReplayError::InvalidName(2, "..".to_string()))
+        );
+        assert_eq!(
+            parse("$ cd /\n$ whoami"),
+            Err(ReplayError::Unparsable(2, "$ whoami".to_string()))
+        );
+        assert_eq!(
+            ReplayError::UnknownDir(4, "b".to_string()).to_string(),
+            "line 4: cd into unlisted directory \"b\""
+        );
+    }
+
+    #[test]
+    fn from_lines_bam() {
+        let file_system = parse(
+            "$ cd /
+            $ ls
+            dir a
+            dir d
+            $ ls /a
+            dir e
+            1 f
+            $ ls a/e
+            2 g
+            $ cd /a/e
+            $ pwd
+            /a/e
+            $ cd ../../d
+            $ ls
+            4 h
+            $ cd ./../a/./e",
+        )
+        .unwrap();
+        assert_eq!(
+            file_system.du(ROOT),
+            "7\t/\n4\t/d\n3\t/a\n2\t/a/e\n".to_string()
+        );
+
+        assert_eq!(
+            parse("$ cd /\n$ ls\ndir a\n$ cd a\n$ pwd\n/"),
+            Err(ReplayError::WrongDir(6, "/a".to_string(), "/".to_string()))
+        );
+        assert_eq!(
+            parse("$ cd /\n$ ls /b"),
+            Err(ReplayError::UnknownLsDir(2, "b".to_string()))
+        );
+    }
+
+    #[test]
+    fn remove_bim() {
+        let file_system = parse(
+            "$ cd /
+            $ ls
+            dir a
+            dir d
+            1 b
+            $ cd a
+            $ ls
+            dir e
+            2 f
+            $ cd e
+            $ ls
+            4 g
+            $ cd /d
+            $ ls
+            8 h
+            $ rm /a/e
+            $ rm ../b
+            $ ls
+            16 i",
+        )
+        .unwrap();
+        assert_eq!(file_system.dirs.len(), 3);
+        assert_eq!(file_system.du(ROOT), "26\t/\n24\t/d\n2\t/a\n".to_string());
+        assert_eq!(file_system.child(ROOT, "d"), Some(2));
+        assert_eq!(file_system.dirs[2].parent, Some(ROOT));
+
+        let mut file_system = example();
+        assert_eq!(file_system.remove(3, "/d", 40), Ok(2));
+        assert_eq!(file_system.sizes(), vec![23447523, 94853, 584]);
+        assert_eq!(file_system.remove(ROOT, "a/e/", 41), Ok(ROOT));
+        assert_eq!(file_system.sizes(), vec![23446939, 94269]);
+        assert_eq!(
+            file_system.remove(1, "e", 42),
+            Err(ReplayError::UnknownPath(42, "e".to_string()))
+        );
+        assert_eq!(
+            file_system.remove(1, "/a", 43),
+            Err(ReplayError::RemovedWorkingDir(43, "/a".to_string()))
+        );
+        assert_eq!(
+            ReplayError::UnknownPath(42, "e".to_string()).to_string(),
+            "line 42: rm of unlisted \"e\""
+        );
+    }
+
+    #[test]
+    fn sizes_boum() {
+        assert_eq!(example().sizes(), vec![48381165, 94853, 24933642, 584]);
+        assert_eq!(FileSystem::new().sizes(), vec![0]);
+    }
+
+    #[test]
+    fn path_bim() {
+        let file_system = example();
+
+        assert_eq!(file_system.path(ROOT), "/");
+        assert_eq!(file_system.path(1), "/a");
+        assert_eq!(file_system.path(3), "/a/e");
+    }
+
+    #[test]
+    fn part1_run_boum() {
+        assert_eq!(part1_run(EXAMPLE, &DiskConfig::default()), 95437);
+        assert_eq!(
+            part1_run(
+                EXAMPLE,
+                &DiskConfig {
+                    small_dir_threshold: 584,
+                    ..DiskConfig::default()
+                }
+            ),
+            584
+        );
+    }
+
+    #[test]
+    fn part2_run_bim() {
+        assert_eq!(
+            part2_run(EXAMPLE, &DiskConfig::default()),
+            Some(("/d".to_string(), 24933642))
+        );
+        assert_eq!(
+            part2_run(
+                EXAMPLE,
+                &DiskConfig {
+                    required_space: 21619000,
+                    ..DiskConfig::default()
+                }
+            ),
+            Some(("/a/e".to_string(), 584))
+        );
+        assert_eq!(
+            part2_run(
+                EXAMPLE,
+                &DiskConfig {
+                    small_dir_threshold: 100000,
+                    total_space: 48381165,
+                    required_space: 50000000,
+                }
+            ),
+            None
+        );
+        assert_eq!(
+            part2_run(
+                EXAMPLE,
+                &DiskConfig {
+                    total_space: 100000000,
+                    ..DiskConfig::default()
+                }
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn part1_bim() {
+        assert_eq!(
+            part1_run(
+                "$ cd /
+                $ ls
+                272 dncdssn.hdr
+                4679 lmw.wmp",
+                &DiskConfig::default()
+            ),
+            272 + 4679
+        );
+        assert_eq!(
+            part1_run(
+                "$ cd /
+                $ ls
+                272 dncdssn.hdr
+                4679 lmw.wmp
+                $ ls
+                272 dncdssn.hdr
+                4679 lmw.wmp",
+                &DiskConfig::default()
+            ),
+            272 + 4679
+        );
+        assert_eq!(
+            part1_run(
+                "$ cd /
+                $ ls
+                100000 dncdssn.hdr
+                dir a
+                dir b
+                $ cd a
+                $ ls
+                100000 lmw.wmp
+                dir aa
+                $ cd aa
+                $ ls
+                100000 lmw.wmp
+                100000 lmw.wmp
+                $ cd ..
+                $ cd ..
+                $ cd b
+                $ ls
+                100000 lmw.wmp
+                $ cd /
+                $ cd b
+                $ ls
+                100000 lmw.wmp",
+                &DiskConfig::default()
+            ),
+            100000 + 100000
         );
-    let files = resulting_line.into_iter().filter_map(|(dir, line)| {
-        if let Line::File(size, ..) = line {
-            Some((dir, size))
-        } else {
-            None
-        }
-    });
-    let dirs = files.flat_map(|(dir, size)| all_dirs(&dir).into_iter().map(move |dir| (dir, size)));
-    let dirs = dirs
-        .fold(HashMap::<String, usize>::new(), |map, (dir, size)| {
-            size_by_dir(map, dir, size)
-        })
-        .into_iter();
-    let answer: usize = dirs
-        .filter(|(_, size)| size <= &100000)
-        .map(|(_, size)| size)
-        .sum();
-    println!("day5, part1: {:?}", answer);
-    answer
-}
+    }
 
-pub fn part2() {
-    let resulting_line: Vec<(String, Line)> = Vec::new();
-    let (_, resulting_line) = input()
-        .split('\n')
-        .into_iter()
-        .map(str::trim)
-        .map(Line::from)
-        .fold(
-            ("root".to_string(), resulting_line),
-            |(current_dir, mut resulting_lines), line| {
-                let next_dir = associate_dir(&current_dir, &line);
-
-                resulting_lines.push((next_dir.clone(), line));
-                (next_dir, resulting_lines)
-            },
-        );
-    let files = resulting_line.into_iter().filter_map(|(dir, line)| {
-        if let Line::File(size, ..) = line {
-            Some((dir, size))
-        } else {
-            None
-        }
-    });
-    let dirs = files.flat_map(|(dir, size)| all_dirs(&dir).into_iter().map(move |dir| (dir, size)));
-    let dirs = dirs.fold(HashMap::<String, usize>::new(), |map, (dir, size)| {
-        size_by_dir(map, dir, size)
-    });
+    #[test]
+    fn add_file_bam() {
+        let mut file_system = example();
 
-    let unused_space = 70000000 - dirs["root"];
-    let space_to_find = 30000000 - unused_space;
+        assert_eq!(file_system.add_file(3, "i".to_string(), 584, 30), Ok(()));
+        assert_eq!(file_system.add_file(3, "j".to_string(), 1, 31), Ok(()));
+        assert_eq!(
+            file_system.add_file(3, "i".to_string(), 585, 32),
+            Err(ReplayError::SizeConflict(
+                32,
+                "/a/e/i".to_string(),
+                584,
+                585
+            ))
+        );
+        assert_eq!(
+            file_system.dirs[3].files,
+            vec![("i".to_string(), 584), ("j".to_string(), 1)]
+        );
+        assert_eq!(
+            file_system.add_file(ROOT, "b.txt".to_string(), 1, 33),
+            Err(ReplayError::SizeConflict(
+                33,
+                "/b.txt".to_string(),
+                14848514,
+                1
+            ))
+        );
+    }
 
-    let mut dirs_size = dirs
-        .into_iter()
-        .map(|(_, size)| size as isize)
-        .collect::<Vec<_>>();
-    dirs_size.sort_by(|size_a, size_b| {
-        positive_close_to_0(
-            size_a - space_to_find as isize,
-            size_b - space_to_find as isize,
-        )
-    });
+    #[test]
+    fn shell_command_bim() {
+        let file_system = example();
 
-    let answer = dirs_size.first();
-    println!("day5, part1: {:?}", answer);
-}
+        assert_eq!(
+            file_system.shell_command(ROOT, "ls", 1),
+            Ok((
+                ROOT,
+                "dir a\ndir d\n14848514 b.txt\n8504156 c.dat\n".to_string()
+            ))
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, " cd a ", 1),
+            Ok((1, "".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(1, "cd ..", 1),
+            Ok((ROOT, "".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(3, "cd /", 1),
+            Ok((ROOT, "".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, "cd ..", 1),
+            Err("line 1: cd .. above /".to_string())
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, "cd e", 1),
+            Err("line 1: cd into unlisted directory \"e\"".to_string())
+        );
+        assert_eq!(
+            file_system.shell_command(1, "du", 1),
+            Ok((1, "94853\t/a\n584\t/a/e\n".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(3, "ls /d", 1),
+            Ok((
+                3,
+                "4060174 j\n8033020 d.log\n5626152 d.ext\n7214296 k\n".to_string()
+            ))
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, "cd /a/e", 1),
+            Ok((3, "".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(3, "pwd", 1),
+            Ok((3, "/a/e\n".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, "rm -rf", 1),
+            Err("unknown command \"rm -rf\"".to_string())
+        );
+    }
 
-#[derive(Debug, PartialEq, Eq)]
-enum Line {
-    Cd(String),
-    Ls,
-    Dir(String),
-    File(usize, String),
-}
+    #[test]
+    fn shell_command_boum() {
+        let file_system = example();
 
-impl From<&str> for Line {
-    fn from(value: &str) -> Self {
-        if let Some(captures) = CD_REGEX.captures(value) {
-            Self::Cd(captures[1].to_string())
-        } else if LS_REGEX.is_match(value) {
-            Self::Ls
-        } else if let Some(captures) = DIR_REGEX.captures(value) {
-            Self::Dir(captures[1].to_string())
-        } else if let Some(captures) = FILE_REGEX.captures(value) {
-            Self::File(captures[1].parse().unwrap(), captures[2].to_string())
-        } else {
-            panic!("parsing line \"{}\" failed", value)
-        }
+        assert_eq!(
+            file_system.shell_command(1, "tree", 1),
+            Ok((
+                1,
+                "- a (dir, size=94853)
+  - e (dir, size=584)
+    - i (file, size=584)
+  - f (file, size=29116)
+  - g (file, size=2557)
+  - h.lst (file, size=62596)
+"
+                .to_string()
+            ))
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, "find d", 1),
+            Ok((ROOT, "/c.dat\n/d\n/d/d.log\n/d/d.ext\n".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(2, "find d", 1),
+            Ok((2, "/d\n/d/d.log\n/d/d.ext\n".to_string()))
+        );
+        assert_eq!(
+            file_system.shell_command(ROOT, "find zzz", 1),
+            Ok((ROOT, "".to_string()))
+        );
     }
-}
 
-fn associate_dir(current_dir: &str, line: &Line) -> String {
-    match &line {
-        Line::Cd(dir) if dir == "/" => "root".to_string(),
-        Line::Cd(dir) if dir == ".." => {
-            PREVIOUS_DIR_REGEX.captures(current_dir).unwrap()[1].to_string()
-        }
-        Line::Cd(dir) => format!("{}/{}", current_dir, dir),
-        _ => current_dir.to_string(),
+    #[test]
+    fn tree_bim() {
+        assert_eq!(
+            example().tree(ROOT),
+            "- / (dir, size=48381165)
+  - a (dir, size=94853)
+    - e (dir, size=584)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir, size=24933642)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+        );
+        assert_eq!(FileSystem::new().tree(ROOT), "- / (dir, size=0)\n");
     }
-}
-
-fn all_dirs(dir: &str) -> Vec<String> {
-    let mut dirs = vec!["root".to_string()];
 
-    let mut current_dir = dir.to_string();
-    while let Some(captures) = PREVIOUS_DIR_REGEX.captures(&current_dir) {
-        dirs.push(current_dir.clone());
-        current_dir = captures[1].to_string();
+    #[test]
+    fn du_boum() {
+        assert_eq!(
+            example().du(ROOT),
+            "48381165\t/\n24933642\t/d\n94853\t/a\n584\t/a/e\n"
+        );
+        assert_eq!(example().du(2), "24933642\t/d\n");
+        assert_eq!(example().descendants(ROOT), vec![ROOT, 1, 3, 2]);
     }
 
-    dirs
-}
-
-fn size_by_dir(
-    mut map: HashMap<String, usize>,
-    dir: String,
-    size: usize,
-) -> HashMap<String, usize> {
-    if let Some(computed_size) = map.get_mut(&dir) {
-        *computed_size += size;
-    } else {
-        map.insert(dir, size);
+    #[test]
+    fn json_bim() {
+        assert_eq!(
+            example().json(1),
+            r#"{"name":"a","type":"dir","size":94853,"children":[{"name":"e","type":"dir","size":584,"children":[{"name":"i","type":"file","size":584}]},{"name":"f","type":"file","size":29116},{"name":"g","type":"file","size":2557},{"name":"h.lst","type":"file","size":62596}]}"#
+        );
+        assert_eq!(
+            FileSystem::new().json(ROOT),
+            r#"{"name":"/","type":"dir","size":0,"children":[]}"#
+        );
+        assert_eq!(json_string("a\"b\\c\n"), r#""a\"b\\c\u000a""#);
     }
-    map
-}
 
-fn positive_close_to_0(a: isize, b: isize) -> Ordering {
-    if a < 0 {
-        return Ordering::Greater;
-    }
-    if b < 0 {
-        return Ordering::Less;
+    #[test]
+    fn csv_boum() {
+        assert_eq!(
+            example().csv(ROOT),
+            "path,size\n/,48381165\n/a,94853\n/a/e,584\n/d,24933642\n"
+        );
+        assert_eq!(csv_field("/a,b"), "\"/a,b\"");
+        assert_eq!(csv_field("/a\"b"), "\"/a\"\"b\"");
     }
-    a.cmp(&b)
-}
 
-#[cfg(test)]
-mod test {
-    use std::cmp::Ordering;
+    #[test]
+    fn smallest_dir_to_free_bim() {
+        let file_system = example();
 
-    use crate::day7::{all_dirs, associate_dir, part1_run, size_by_dir, Line};
+        assert_eq!(file_system.space_to_free(&DiskConfig::default()), 8381165);
+        assert_eq!(
+            file_system.smallest_dir_to_free(8381165),
+            Some(("/d".to_string(), 24933642))
+        );
+        assert_eq!(
+            file_system.smallest_dir_to_free(1),
+            Some(("/a/e".to_string(), 584))
+        );
+        assert_eq!(file_system.smallest_dir_to_free(48381166), None);
 
-    use super::positive_close_to_0;
+        let config = DiskConfig {
+            small_dir_threshold: 100000,
+            total_space: 100000000,
+            required_space: 30000000,
+        };
+        assert_eq!(file_system.space_to_free(&config), 0);
+        assert_eq!(file_system.smallest_dir_to_free(0), None);
+        assert_eq!(file_system.deletion_plan(0), Some((vec![], 0)));
+    }
 
     #[test]
-    fn parse_line_bim() {
-        assert_eq!(Line::from("$ cd boum"), Line::Cd("boum".to_string()));
-        assert_eq!(Line::from("$ ls"), Line::Ls);
-        assert_eq!(Line::from("dir bim"), Line::Dir("bim".to_string()));
+    fn deletion_plan_bim() {
+        let file_system = example();
+
         assert_eq!(
-            Line::from("1996 boum"),
-            Line::File(1996, "boum".to_string())
+            file_system.deletion_plan(8381165),
+            Some((vec!["/d".to_string()], 24933642))
+        );
+        assert_eq!(file_system.deletion_plan(0), Some((vec![], 0)));
+        assert_eq!(
+            file_system.deletion_plan(500),
+            Some((vec!["/a/e".to_string()], 584))
         );
+        assert_eq!(file_system.deletion_plan(48381166), None);
     }
 
     #[test]
-    fn associate_dir_boum() {
+    fn deletion_plan_boum() {
+        let file_system = parse(
+            "$ cd /
+            $ ls
+            dir a
+            dir b
+            dir c
+            $ cd a
+            $ ls
+            60 f
+            $ cd ..
+            $ cd b
+            $ ls
+            50 f
+            dir d
+            $ cd d
+            $ ls
+            45 f
+            $ cd ..
+            $ cd ..
+            $ cd c
+            $ ls
+            30 f",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file_system.deletion_plan(72),
+            Some((vec!["/b/d".to_string(), "/c".to_string()], 75))
+        );
         assert_eq!(
-            associate_dir("root", &Line::Cd("boum".to_string())),
-            "root/boum".to_string()
+            file_system.deletion_plan(90),
+            Some((vec!["/a".to_string(), "/c".to_string()], 90))
         );
         assert_eq!(
-            associate_dir("root/bim", &Line::Cd("boum".to_string())),
-            "root/bim/boum".to_string()
+            file_system.deletion_plan(91),
+            Some((vec!["/b".to_string()], 95))
         );
         assert_eq!(
-            associate_dir("root/bim", &Line::Cd("..".to_string())),
-            "root".to_string()
+            file_system.deletion_plan(96),
+            Some((vec!["/a".to_string(), "/b/d".to_string()], 105))
         );
+        assert_eq!(file_system.deletion_plan(186), None);
+    }
+
+    #[test]
+    fn deletion_plan_bam() {
+        let file_system = parse(
+            "$ cd /
+            $ ls
+            dir a
+            dir b
+            dir c
+            $ cd a
+            $ ls
+            dir d
+            7340032 f
+            $ cd d
+            $ ls
+            52428853 f
+            $ cd /b
+            $ ls
+            31457280 f
+            dir e
+            $ cd e
+            $ ls
+            20971520 f
+            $ cd /c
+            $ ls
+            41943040 f",
+        )
+        .unwrap();
+
         assert_eq!(
-            associate_dir("root/bim", &Line::Cd("/".to_string())),
-            "root".to_string()
+            file_system.deletion_plan(62914560),
+            Some((vec!["/b/e".to_string(), "/c".to_string()], 62914560))
         );
         assert_eq!(
-            associate_dir("root/bim/boum", &Line::Cd("/".to_string())),
-            "root".to_string()
+            file_system.deletion_plan(73400321),
+            Some((vec!["/a/d".to_string(), "/b/e".to_string()], 73400373))
+        );
+        assert_eq!(
+            file_system.deletion_plan(94371840),
+            Some((vec!["/b".to_string(), "/c".to_string()], 94371840))
         );
-
-        assert_eq!(associate_dir("/boum", &Line::Ls), "/boum".to_string(),);
-        assert_eq!(associate_dir("/", &Line::Ls), "/".to_string());
     }
 
     #[test]
-    fn all_dirs_bim() {
-        assert_eq!(all_dirs("root"), vec!["root".to_string()]);
+    fn generate_log_bim() {
+        let root = env::temp_dir().join(format!("day7_generate_log_{}", std::process::id()));
+        fs::create_dir_all(root.join("a/e")).unwrap();
+        fs::create_dir_all(root.join("d")).unwrap();
+        fs::write(root.join("b.txt"), vec![0; 1484]).unwrap();
+        fs::write(root.join("a/f"), vec![0; 291]).unwrap();
+        fs::write(root.join("a/e/i"), vec![0; 584]).unwrap();
+        fs::write(root.join("d/j"), vec![0; 40]).unwrap();
+
+        let log = generate_log(&root, None).unwrap();
         assert_eq!(
-            all_dirs("root/boum"),
-            vec!["root".to_string(), "root/boum".to_string()]
+            log,
+            "$ cd /
+$ ls
+dir a
+1484 b.txt
+dir d
+$ cd a
+$ ls
+dir e
+291 f
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+40 j
+$ cd .."
         );
+
+        let expected = parse(&log)
+            .unwrap()
+            .sizes()
+            .into_iter()
+            .sorted()
+            .collect::<Vec<_>>();
+        for seed in [1, 7, 1996] {
+            let shuffled = generate_log(&root, Some(seed)).unwrap();
+            assert_eq!(
+                parse(&shuffled)
+                    .unwrap()
+                    .sizes()
+                    .into_iter()
+                    .sorted()
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn generate_log_boum() {
+        let root = env::temp_dir().join(format!("day7_generate_log_names_{}", std::process::id()));
+        fs::create_dir_all(root.join("dir x/my docs")).unwrap();
+        fs::write(root.join("notes dir x.txt"), vec![0; 12]).unwrap();
+        fs::write(root.join("dir x/12 b"), vec![0; 5]).unwrap();
+        fs::write(root.join("dir x/my docs/$ cd y"), vec![0; 7]).unwrap();
+
+        let file_system = parse(&generate_log(&root, None).unwrap()).unwrap();
         assert_eq!(
-            all_dirs("root/boum/bim"),
-            vec![
-                "root".to_string(),
-                "root/boum/bim".to_string(),
-                "root/boum".to_string()
-            ]
+            file_system.tree(ROOT),
+            "- / (dir, size=24)
+  - dir x (dir, size=12)
+    - 12 b (file, size=5)
+    - my docs (dir, size=7)
+      - $ cd y (file, size=7)
+  - notes dir x.txt (file, size=12)
+"
         );
+
+        fs::remove_dir_all(root).unwrap();
     }
 
     #[test]
-    fn size_by_dir_boum() {
+    fn materialize_bim() {
+        let target = env::temp_dir().join(format!("day7_materialize_{}", std::process::id()));
+        example().materialize(&target).unwrap();
+
+        assert_eq!(fs::metadata(target.join("a/e/i")).unwrap().len(), 584);
+        assert_eq!(fs::metadata(target.join("b.txt")).unwrap().len(), 14848514);
         assert_eq!(
-            size_by_dir(vec![].into_iter().collect(), "root".to_string(), 1),
-            vec![("root".to_string(), 1)].into_iter().collect()
+            parse(&generate_log(&target, None).unwrap())
+                .unwrap()
+                .du(ROOT),
+            example().du(ROOT)
         );
 
+        fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn materialize_bam() {
+        let target = env::temp_dir().join(format!("day7_materialize_find_{}", std::process::id()));
+        let file_system = &example();
+        file_system.materialize(&target).unwrap();
+
+        let output = process::Command::new("find")
+            .args([
+                target.to_str().unwrap(),
+                "-type",
+                "f",
+                "-printf",
+                "%s /%P\n",
+            ])
+            .output()
+            .unwrap();
+        let found: Vec<(usize, String)> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let (size, path) = line.split_once(' ').unwrap();
+                (size.parse().unwrap(), path.to_string())
+            })
+            .sorted()
+            .collect();
+        let expected: Vec<(usize, String)> = file_system
+            .descendants(ROOT)
+            .into_iter()
+            .flat_map(|dir| {
+                file_system.dirs[dir]
+                    .files
+                    .iter()
+                    .map(move |(name, size)| (*size, file_system.file_path(dir, name)))
+            })
+            .sorted()
+            .collect();
+        assert_eq!(found, expected);
         assert_eq!(
-            size_by_dir(
-                vec![("root".to_string(), 2)].into_iter().collect(),
-                "root".to_string(),
-                1
-            ),
-            vec![("root".to_string(), 3)].into_iter().collect()
+            found.iter().map(|(size, _)| size).sum::<usize>(),
+            file_system.sizes()[ROOT]
         );
+
+        fs::remove_dir_all(target).unwrap();
     }
 
-    fn part1_bim() {
+    #[test]
+    fn materialize_boum() {
+        let target = env::temp_dir().join(format!("day7_materialize_full_{}", std::process::id()));
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("b.txt"), "keep").unwrap();
+
+        let error = example().materialize(&target).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(target.join("b.txt")).unwrap(), "keep");
+        assert!(!target.join("a").exists());
+        fs::remove_dir_all(&target).unwrap();
+
+        let file_system = parse(
+            "$ cd /
+            $ ls
+            dir a
+            5 b
+            $ cd a
+            $ ls
+            dir c
+            3 c",
+        )
+        .unwrap();
+        let error = file_system.materialize(&target).unwrap_err();
+        assert_eq!(error.to_string(), "/a/c is both a file and a directory");
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn check_log_bim() {
+        assert_eq!(check_log(EXAMPLE), vec![]);
         assert_eq!(
-            part1_run(
+            check_log(
                 "$ cd /
                 $ ls
-                272080 dncdssn.hdr
-                4679 lmw.wmp"
+                dir a
+                1 b
+                $ cd a
+                2 c
+                $ cd /
+                $ ls
+                1 b
+                dir a
+                $ cd e/f
+                $ ls
+                3 g
+                $ cd /
+                $ ls
+                dir a
+                1 b
+                4 h
+                $ cd ../a
+                $ pwd
+                /
+                $ whoami"
             ),
-            272080 + 4679
+            vec![
+                ReplayError::UnlistedOutput(6, "2 c".to_string()),
+                ReplayError::UnknownDir(11, "e".to_string()),
+                ReplayError::UnknownDir(11, "f".to_string()),
+                ReplayError::ListingChanged(18, "/".to_string(), 2),
+                ReplayError::AboveRoot(19),
+                ReplayError::WrongDir(21, "/a".to_string(), "/".to_string()),
+                ReplayError::Unparsable(22, "$ whoami".to_string()),
+            ]
         );
         assert_eq!(
-            part1_run(
-                "$ cd /
-                $ ls
-                272080 dncdssn.hdr
-                $ cd azd
-                4679 lmw.wmp"
-            ),
-            272080 + 4679
+            check_log("$ ls\n1 b\n$ rm b\n$ ls\n$ ls\n2 b"),
+            vec![ReplayError::ListingChanged(6, "/".to_string(), 4)]
         );
         assert_eq!(
-            part1_run(
-                "$ cd /
-                $ ls
-                100000 dncdssn.hdr
-                $ cd azd
-                100000 lmw.wmp
-                $ cd azd
-                100000 lmw.wmp"
-            ),
-            100000 + 200000 + 300000
+            check_log("$ ls\n1 b\n$ ls\n2 b"),
+            vec![ReplayError::ListingChanged(4, "/".to_string(), 1)]
         );
         assert_eq!(
-            part1_run(
-                "$ cd /
-                $ ls
-                100000 dncdssn.hdr
-                $ cd a
-                100000 lmw.wmp
-                $ cd aa
-                100000 lmw.wmp
-                $ cd ..
-                $ cd ..
-                $ cd b
-                100000 lmw.wmp"
-            ),
-            100000 + 100000 + 200000 + 400000
+            check_log("$ ls\n1 b\ndir a\n$ ls\ndir a\n$ ls\ndir a\n1 b\n3 a"),
+            vec![
+                ReplayError::ListingChanged(4, "/".to_string(), 1),
+                ReplayError::ListingChanged(9, "/".to_string(), 1),
+            ]
         );
         assert_eq!(
-            part1_run(
-                "$ cd /
-                $ ls
-                100000 dncdssn.hdr
-                $ cd a
-                100000 lmw.wmp
-                $ cd aa
-                100000 lmw.wmp
-                100000 lmw.wmp
-                $ cd ..
-                $ cd ..
-                $ cd b
-                100000 lmw.wmp"
-            ),
-            100000 + 200000 + 300000 + 500000
+            check_log("$ ls\n1 b\n2 b\n$ ls\n1 b\n2 b\n$ ls\n1 b\n3 b"),
+            vec![
+                ReplayError::SizeConflict(3, "/b".to_string(), 1, 2),
+                ReplayError::ListingChanged(9, "/".to_string(), 1),
+            ]
         );
         assert_eq!(
-            part1_run(
-                "$ cd /
-                $ ls
-                100000 dncdssn.hdr
-                $ cd a
-                100000 lmw.wmp
-                $ cd aa
-                100000 lmw.wmp
-                100000 lmw.wmp
-                $ cd ..
-                $ cd ..
-                $ cd b
-                100000 lmw.wmp"
-            ),
-            100000 + 200000 + 300000 + 500000
+            check_log("$ cd /\n/\n$ pwd\n/\n/"),
+            vec![
+                ReplayError::UnlistedOutput(2, "/".to_string()),
+                ReplayError::UnlistedOutput(5, "/".to_string()),
+            ]
+        );
+        assert_eq!(
+            check_log("$ ls\n1 b\ndir a\ndir c\n$ ls a\n2 d\n$ rm b\n$ ls\ndir a\n$ ls a\n3 d"),
+            vec![ReplayError::ListingChanged(11, "/a".to_string(), 5)]
+        );
+        assert_eq!(
+            check_log("$ ls\ndir a\n$ ls a\ndir e\n$ rm a\n$ ls\ndir a\n$ ls a/e\n$ ls a\n1 f"),
+            vec![ReplayError::UnknownLsDir(8, "e".to_string())]
+        );
+        assert_eq!(
+            check_log("$ ls -la\n$ ls\n99999999999999999999999 big"),
+            vec![
+                ReplayError::UnknownLsDir(1, "-la".to_string()),
+                ReplayError::Unparsable(3, "99999999999999999999999 big".to_string()),
+            ]
+        );
+        assert_eq!(
+            ReplayError::UnknownLsDir(1, "-la".to_string()).to_string(),
+            "line 1: ls of unlisted directory \"-la\""
+        );
+        assert_eq!(
+            ReplayError::ListingChanged(15, "/".to_string(), 2).to_string(),
+            "line 15: / listed differently than on line 2"
         );
     }
 
     #[test]
-    fn positive_close_to_0_bmou() {
-        assert_eq!(positive_close_to_0(-1, 10), Ordering::Greater);
-        assert_eq!(positive_close_to_0(10, -1), Ordering::Less);
-        assert_eq!(positive_close_to_0(10, 10), Ordering::Equal);
-        assert_eq!(positive_close_to_0(1, 10), Ordering::Less);
+    fn or_shifted_bam() {
+        let mut target = vec![0b1, 0];
+        or_shifted(&mut target, &[0b11, 0], 63);
+        assert_eq!(target, vec![0b1 | 1 << 63, 0b1]);
+
+        let mut target = vec![0, 0];
+        or_shifted(&mut target, &[0b101, 1 << 63], 64);
+        assert_eq!(target, vec![0, 0b101]);
+    }
+
+    #[test]
+    fn or_shifted_in_place_bim() {
+        for shift in [0, 1, 63, 64, 65, 130] {
+            let row = vec![0b1011 | 1 << 62, 1 << 63 | 0b1, 0, 0];
+            let mut expected = row.clone();
+            or_shifted(&mut expected, &row, shift);
+
+            let mut shifted = row;
+            or_shifted_in_place(&mut shifted, shift);
+            assert_eq!(shifted, expected, "shift {}", shift);
+        }
+    }
+
+    #[test]
+    fn disk_config_from_args_boum() {
+        assert_eq!(DiskConfig::from_args(&[]), DiskConfig::default());
+        assert_eq!(
+            DiskConfig::from_args(&["--required", "10", "--threshold", "20"]),
+            DiskConfig {
+                small_dir_threshold: 20,
+                total_space: 70000000,
+                required_space: 10
+            }
+        );
     }
 }
 
@@ -7,7 +7,113 @@ mod day5;
 mod day6;
 mod day7;
 
+use std::thread;
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    if let ["day5", "provenance", part, stack @ ..] = args.as_slice() {
+        let stack = stack.first().map(|stack| stack.parse().unwrap());
+        return day5::provenance(*part == "part2", stack);
+    }
+    if let ["day5", "streaming", part] = args.as_slice() {
+        return match *part {
+            "part2" => day5::part2_streaming(),
+            _ => day5::part1_streaming(),
+        };
+    }
+    if let ["day5", "bench", crates, moves] = args.as_slice() {
+        return day5::bench(crates.parse().unwrap(), moves.parse().unwrap());
+    }
+    if let ["day5", "reverse", part, final_yard] = args.as_slice() {
+        return day5::reverse(*part == "part2", final_yard);
+    }
+    if let ["day5", "optimize"] = args.as_slice() {
+        return day5::optimize();
+    }
+    if let ["day5", "plan", part, start, target] = args.as_slice() {
+        return day5::plan(*part == "part2", start, target);
+    }
+    if let ["day6", "bench", size] = args.as_slice() {
+        return day6::bench(size.parse().unwrap());
+    }
+    if let ["day6", "part1", mode @ ..] = args.as_slice() {
+        return day6::part1_with(day6::Mode::from_args(mode));
+    }
+    if let ["day6", "part2", mode @ ..] = args.as_slice() {
+        return day6::part2_with(day6::Mode::from_args(mode));
+    }
+    if let ["day6", "stream", "--chars", lenght, path @ ..] = args.as_slice() {
+        return day6::stream(
+            day6::Mode::Chars,
+            lenght.parse().unwrap(),
+            path.first().copied(),
+        );
+    }
+    if let ["day6", "stream", lenght, path @ ..] = args.as_slice() {
+        return day6::stream(
+            day6::Mode::Bytes,
+            lenght.parse().unwrap(),
+            path.first().copied(),
+        );
+    }
+    if let ["day6", "parallel", lenght, rest @ ..] = args.as_slice() {
+        let (threads, path) = match rest {
+            ["--threads", threads, path @ ..] | [path @ .., "--threads", threads] => {
+                (threads.parse().unwrap(), path)
+            }
+            path => (thread::available_parallelism().unwrap().get(), path),
+        };
+        let path = match path {
+            [] => None,
+            [path] => Some(*path),
+            _ => panic!("unknown arguments {:?}", path),
+        };
+        return day6::parallel(lenght.parse().unwrap(), threads, path);
+    }
+    if let ["day7", "part1", flags @ ..] = args.as_slice() {
+        day7::part1_with(&day7::DiskConfig::from_args(flags));
+        return;
+    }
+    if let ["day7", "part2", flags @ ..] = args.as_slice() {
+        return day7::part2_with(&day7::DiskConfig::from_args(flags));
+    }
+    if let ["day7", "plan", rest @ ..] = args.as_slice() {
+        let (path, flags) = match rest {
+            [path, flags @ ..] if !path.starts_with("--") => (Some(*path), flags),
+            flags => (None, flags),
+        };
+        return day7::plan(&day7::DiskConfig::from_args(flags), path);
+    }
+    if let ["day7", "generate", path, seed @ ..] = args.as_slice() {
+        return day7::generate(path, seed.first().map(|seed| seed.parse().unwrap()));
+    }
+    if let ["day7", "tree", path @ ..] = args.as_slice() {
+        return day7::tree(path.first().copied());
+    }
+    if let ["day7", "du", path @ ..] = args.as_slice() {
+        return day7::du(path.first().copied());
+    }
+    if let ["day7", "json", path @ ..] = args.as_slice() {
+        return day7::json(path.first().copied());
+    }
+    if let ["day7", "csv", path @ ..] = args.as_slice() {
+        return day7::csv(path.first().copied());
+    }
+    if let ["day7", "materialize", target, path @ ..] = args.as_slice() {
+        return day7::materialize(target, path.first().copied());
+    }
+    if let ["day7", "check", path @ ..] = args.as_slice() {
+        return day7::check(path.first().copied());
+    }
+    if let ["day7", "shell", path @ ..] = args.as_slice() {
+        return day7::shell(path.first().copied());
+    }
+    if let ["day6", "markers", mode @ ..] = args.as_slice() {
+        return day6::markers(mode.first() != Some(&"non-overlapping"));
+    }
+
     // day3::part1();
     // day3::part2();
     // day4::part1();
@@ -16,6 +122,8 @@ fn main() {
     // day5::part2();
     // day6::part1();
     // day6::part2();
+    // day6::both_parts();
+    // day6::decode();
     day7::part1();
     day7::part2();
 }